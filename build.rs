@@ -6,7 +6,7 @@ use std::fs::{self, File};
 use std::path::Path;
 use std::{cmp, env, error};
 
-use ignore::Walk;
+use ignore::WalkBuilder;
 use serde_json::Value;
 
 fn main() -> Result<(), Box<dyn error::Error>> {
@@ -20,9 +20,10 @@ fn main() -> Result<(), Box<dyn error::Error>> {
 fn generate_languages(out_dir: &OsStr) -> Result<(), Box<dyn error::Error>> {
     let mut tera = tera::Tera::default();
 
-    let mut json: Value = serde_json::from_reader(File::open(&"languages.json")?)?;
+    let mut json: Value = serde_json::from_reader(File::open("languages.json")?)?;
+    let embeddings = json["embeddings"].clone();
 
-    for (_key, ref mut item) in json
+    for (key, ref mut item) in json
         .get_mut("languages")
         .unwrap()
         .as_object_mut()
@@ -42,6 +43,26 @@ fn generate_languages(out_dir: &OsStr) -> Result<(), Box<dyn error::Error>> {
         sort_prop!("quotes");
         sort_prop!("verbatim_quotes");
         sort_prop!("multi_line");
+
+        // Languages name one or more sets of rules from `embeddings`, which
+        // are inlined so the template can use them directly.
+        if let Some(embedding) = item.get_mut("embedding") {
+            let names = match &*embedding {
+                Value::Array(names) => names.clone(),
+                name => vec![name.clone()],
+            };
+            let mut rules = Vec::new();
+
+            for name in names {
+                let name = name.as_str().expect("embedding as str");
+                let set = embeddings
+                    .get(name)
+                    .unwrap_or_else(|| panic!("{} uses unknown embedding {:?}", key, name));
+                rules.extend_from_slice(set.as_array().expect("embedding set as array"));
+            }
+
+            *embedding = Value::Array(rules);
+        }
     }
 
     let output_path = Path::new(&out_dir).join("language_type.rs");
@@ -80,16 +101,20 @@ fn generate_tests_batch(
     test_module: Option<&str>,
     string: &mut String,
 ) -> Result<(), Box<dyn error::Error>> {
-    let walker = Walk::new(src_dir).filter(|p| match p {
-        Ok(ref p) => {
-            if let Ok(ref p) = p.metadata() {
-                p.is_file()
-            } else {
-                false
+    // Some files are only recognised at hidden paths, like `.gitlab-ci.yml`.
+    let walker = WalkBuilder::new(src_dir)
+        .hidden(false)
+        .build()
+        .filter(|p| match p {
+            Ok(ref p) => {
+                if let Ok(ref p) = p.metadata() {
+                    p.is_file()
+                } else {
+                    false
+                }
             }
-        }
-        _ => false,
-    });
+            _ => false,
+        });
 
     if let Some(test_module) = test_module {
         string.push_str(&format!(
@@ -107,7 +132,14 @@ use super::*;
         let path = path.path();
         let root = std::path::PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
 
-        let name = path.file_stem().unwrap().to_str().unwrap().to_lowercase();
+        let name = path
+            .file_stem()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .trim_start_matches('.')
+            .to_lowercase()
+            .replace(|c: char| !c.is_alphanumeric(), "_");
 
         if name == "jupyter" {
             continue;
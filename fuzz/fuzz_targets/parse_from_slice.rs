@@ -13,23 +13,11 @@ pub struct FuzzInput<'a> {
 // The first byte of data is used to select a language; remaining input is parsed
 // If check_total is true, asserts that the parsed stats pass a basic sanity test
 pub fn parse_from_slice(input: FuzzInput, check_total: bool) {
-    let config = &Config {
-        treat_doc_strings_as_comments: Some(input.treat_doc_strings_as_comments),
-
-        // these options don't impact the behaviour of parse_from_slice:
-        columns: None,
-        hidden: None,
-        no_ignore: None,
-        no_ignore_parent: None,
-        no_ignore_dot: None,
-        no_ignore_vcs: None,
-        sort: None,
-        types: None,
-        for_each_fn: None,
-    };
+    let mut config = Config::default();
+    config.treat_doc_strings_as_comments = Some(input.treat_doc_strings_as_comments);
 
     // check that parsing doesn't panic
-    let stats = input.lang.parse_from_slice(input.data, config);
+    let stats = input.lang.parse_from_slice(input.data, &config);
 
     if check_total {
         // verify that the parsed total lines is not more than the total occurrences of \n and \r\n.
@@ -1,5 +1,4 @@
-use std::mem;
-use std::process;
+use std::{collections::BTreeMap, process};
 
 use clap::Arg;
 use clap::{crate_description, ArgMatches};
@@ -31,25 +30,52 @@ impl std::str::FromStr for Streaming {
     }
 }
 
+/// Used for grouping files other than by language.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum GroupBy {
+    /// The owners of the files in the `CODEOWNERS` file.
+    Owner,
+    /// The groups of files in the config files.
+    Group,
+}
+
+impl std::str::FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_ref() {
+            "owner" => GroupBy::Owner,
+            "group" => GroupBy::Group,
+            s => return Err(format!("Unsupported grouping option: {}", s)),
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct Cli {
     matches: ArgMatches,
+    pub by_dir: Option<usize>,
     pub columns: Option<usize>,
     pub files: bool,
+    pub group_by: Option<GroupBy>,
     pub hidden: bool,
+    pub metadata: bool,
     pub no_ignore: bool,
     pub no_ignore_parent: bool,
     pub no_ignore_dot: bool,
     pub no_ignore_vcs: bool,
     pub output: Option<Format>,
+    pub path_prefixes: Vec<(String, String)>,
     pub streaming: Option<Streaming>,
     pub print_languages: bool,
+    pub print_schema: bool,
     pub sort: Option<Sort>,
     pub sort_reverse: bool,
     pub types: Option<Vec<LanguageType>>,
     pub compact: bool,
+    pub distribution: bool,
+    pub top: Option<usize>,
     pub number_format: num_format::CustomFormat,
-    pub verbose: u64,
 }
 
 impl Cli {
@@ -62,6 +88,40 @@ impl Cli {
                 "\n",
                 "Support this project on GitHub Sponsors: https://github.com/sponsors/XAMPPRocky"
             ))
+            .arg(
+                Arg::new("by_dir")
+                    .long("by-dir")
+                    .takes_value(true)
+                    .value_name("DEPTH")
+                    .min_values(0)
+                    .require_equals(true)
+                    .default_missing_value("1")
+                    .conflicts_with("files")
+                    .help(
+                        "Prints statistics for each directory, rolled up DEPTH directories \
+                        below the counted paths (1 by default).",
+                    ),
+            )
+            .arg(
+                Arg::new("compare")
+                    .long("compare")
+                    .takes_value(true)
+                    .value_name("OLD")
+                    .conflicts_with_all(&["by_dir", "group_by", "output", "streaming"])
+                    .help(
+                        "Prints the changes from the results of a previous tokei run. Can be \
+                        given a file path, or \"stdin\" to read from stdin.",
+                    ),
+            )
+            .arg(
+                Arg::new("distribution")
+                    .long("distribution")
+                    .conflicts_with_all(&["by_dir", "compare", "group_by"])
+                    .help(
+                        "Prints the mean, median, 90th percentile, and largest number of lines \
+                        per file of each language.",
+                    ),
+            )
             .arg(
                 Arg::new("columns")
                     .long("columns")
@@ -81,10 +141,32 @@ impl Cli {
                     .multiple_values(true)
                     .help("Ignore all files & directories matching the pattern."),
             )
+            .arg(
+                Arg::new("explain")
+                    .long("explain")
+                    .takes_value(true)
+                    .value_name("FILE")
+                    .conflicts_with_all(&[
+                        "by_dir",
+                        "compare",
+                        "distribution",
+                        "group_by",
+                        "input",
+                        "languages",
+                        "output",
+                        "streaming",
+                        "top",
+                    ])
+                    .help(
+                        "Prints every line of a file along with what it was counted as, and \
+                        the rule that decided it.",
+                    ),
+            )
             .arg(
                 Arg::new("files")
                     .long("files")
                     .short('f')
+                    .global(true)
                     .help("Will print out statistics on individual files."),
             )
             .arg(
@@ -92,9 +174,24 @@ impl Cli {
                     .long("input")
                     .short('i')
                     .takes_value(true)
+                    .multiple_occurrences(true)
                     .help(
                         "Gives statistics from a previous tokei run. Can be given a file path, \
-                        or \"stdin\" to read from stdin.",
+                        or \"stdin\" to read from stdin. Can be given more than once to \
+                        merge the statistics of several runs.",
+                    ),
+            )
+            .arg(
+                Arg::new("group_by")
+                    .long("group-by")
+                    .takes_value(true)
+                    .possible_values(["owner", "group"])
+                    .ignore_case(true)
+                    .conflicts_with_all(&["by_dir", "files"])
+                    .help(
+                        "Prints statistics for each owner in the CODEOWNERS file of the \
+                        repository containing the paths, or each of the groups in the \
+                        config files, along with the files without owners or groups.",
                     ),
             )
             .arg(
@@ -115,6 +212,15 @@ impl Cli {
                     .conflicts_with("input")
                     .help("Prints out supported languages and their extensions."),
             )
+            .arg(
+                Arg::new("metadata")
+                    .long("metadata")
+                    .requires("output")
+                    .help(
+                        "Wraps the output in an envelope recording the tokei version, paths, \
+                        options, time, and git commit of the run.",
+                    ),
+            )
             .arg(Arg::new("no_ignore").long("no-ignore").help(
                 "Don't respect ignore files (.gitignore, .ignore, etc.). This implies \
                 --no-ignore-parent, --no-ignore-dot, and --no-ignore-vcs.",
@@ -142,11 +248,38 @@ impl Cli {
                         more format support.",
                     ),
             )
+            .arg(
+                Arg::new("print_schema")
+                    .long("print-schema")
+                    .help("Prints the JSON Schema of the JSON output."),
+            )
+            .arg(
+                Arg::new("replace_prefix")
+                    .long("replace-prefix")
+                    .takes_value(true)
+                    .value_name("FROM=TO")
+                    .multiple_occurrences(true)
+                    .help(
+                        "Replaces the prefix FROM of the paths in reports with TO. Can be \
+                        given more than once, and the longest matching prefix is replaced.",
+                    ),
+            )
+            .arg(
+                Arg::new("strip_prefix")
+                    .long("strip-prefix")
+                    .takes_value(true)
+                    .value_name("PREFIX")
+                    .multiple_occurrences(true)
+                    .help(
+                        "Strips the prefix PREFIX from the paths in reports. Can be given more \
+                        than once.",
+                    ),
+            )
             .arg(
                 Arg::new("streaming")
                     .long("streaming")
                     .takes_value(true)
-                    .possible_values(&["simple", "json"])
+                    .possible_values(["simple", "json"])
                     .ignore_case(true)
                     .help(
                         "prints the (language, path, lines, blanks, code, comments) records as \
@@ -158,7 +291,7 @@ impl Cli {
                     .long("sort")
                     .short('s')
                     .takes_value(true)
-                    .possible_values(&["files", "lines", "blanks", "code", "comments"])
+                    .possible_values(["files", "lines", "blanks", "code", "comments"])
                     .ignore_case(true)
                     .conflicts_with("rsort")
                     .help("Sort languages based on column"),
@@ -168,11 +301,19 @@ impl Cli {
                     .long("rsort")
                     .short('r')
                     .takes_value(true)
-                    .possible_values(&["files", "lines", "blanks", "code", "comments"])
+                    .possible_values(["files", "lines", "blanks", "code", "comments"])
                     .ignore_case(true)
                     .conflicts_with("sort")
                     .help("Reverse sort languages based on column"),
             )
+            .arg(
+                Arg::new("top")
+                    .long("top")
+                    .takes_value(true)
+                    .value_name("N")
+                    .conflicts_with_all(&["by_dir", "compare", "group_by"])
+                    .help("Prints the N largest files of all languages, and of each language."),
+            )
             .arg(
                 Arg::new("types")
                     .long("types")
@@ -214,18 +355,41 @@ impl Cli {
                         3: enable file level trace. Not recommended on multiple files",
                     ),
             )
+            .subcommand(
+                clap::App::new("diff")
+                    .about("Prints the changes between the results of two tokei runs.")
+                    .arg(
+                        Arg::new("old")
+                            .required(true)
+                            .help("The results to compare against, as a file path or \"stdin\"."),
+                    )
+                    .arg(
+                        Arg::new("new")
+                            .required(true)
+                            .help("The results to compare, as a file path or \"stdin\"."),
+                    ),
+            )
             .get_matches();
 
+        let by_dir = matches.value_of("by_dir").map(parse_or_exit::<usize>);
         let columns = matches.value_of("columns").map(parse_or_exit::<usize>);
-        let files = matches.is_present("files");
+        let files = matches.is_present("files")
+            || matches
+                .subcommand_matches("diff")
+                .is_some_and(|matches| matches.is_present("files"));
+        let group_by = matches.value_of("group_by").map(parse_or_exit::<GroupBy>);
         let hidden = matches.is_present("hidden");
+        let metadata = matches.is_present("metadata");
         let no_ignore = matches.is_present("no_ignore");
         let no_ignore_parent = matches.is_present("no_ignore_parent");
         let no_ignore_dot = matches.is_present("no_ignore_dot");
         let no_ignore_vcs = matches.is_present("no_ignore_vcs");
         let print_languages = matches.is_present("languages");
+        let print_schema = matches.is_present("print_schema");
         let verbose = matches.occurrences_of("verbose");
         let compact = matches.is_present("compact");
+        let distribution = matches.is_present("distribution");
+        let top = matches.value_of("top").map(parse_or_exit::<usize>);
         let types = matches.value_of("types").map(|e| {
             e.split(',')
                 .map(str::parse::<LanguageType>)
@@ -258,6 +422,28 @@ impl Cli {
         // is supported) but this will fail if support is not compiled in and
         // give a useful error to the user.
         let output = matches.value_of("output").map(parse_or_exit::<Format>);
+        let path_prefixes = matches
+            .values_of("strip_prefix")
+            .into_iter()
+            .flatten()
+            .map(|prefix| (prefix.to_owned(), String::new()))
+            .chain(
+                matches
+                    .values_of("replace_prefix")
+                    .into_iter()
+                    .flatten()
+                    .map(|replacement| match replacement.split_once('=') {
+                        Some((from, to)) => (from.to_owned(), to.to_owned()),
+                        None => {
+                            eprintln!(
+                                "Error:\n--replace-prefix expects FROM=TO, got: {}",
+                                replacement
+                            );
+                            process::exit(1);
+                        }
+                    }),
+            )
+            .collect();
         let streaming = matches
             .value_of("streaming")
             .map(parse_or_exit::<Streaming>);
@@ -266,22 +452,28 @@ impl Cli {
 
         let cli = Cli {
             matches,
+            by_dir,
             columns,
             files,
+            group_by,
             hidden,
+            metadata,
             no_ignore,
             no_ignore_parent,
             no_ignore_dot,
             no_ignore_vcs,
             output,
+            path_prefixes,
             streaming,
             print_languages,
+            print_schema,
             sort,
             sort_reverse,
             types,
             compact,
+            distribution,
+            top,
             number_format,
-            verbose,
         };
 
         debug!("CLI Config: {:#?}", cli);
@@ -289,8 +481,25 @@ impl Cli {
         cli
     }
 
-    pub fn file_input(&self) -> Option<&str> {
-        self.matches.value_of("file_input")
+    pub fn explain(&self) -> Option<&str> {
+        self.matches.value_of("explain")
+    }
+
+    pub fn compare(&self) -> Option<&str> {
+        self.matches.value_of("compare")
+    }
+
+    /// The old and new results given to the `diff` subcommand.
+    pub fn diff(&self) -> Option<(&str, &str)> {
+        let matches = self.matches.subcommand_matches("diff")?;
+
+        Some((matches.value_of("old")?, matches.value_of("new")?))
+    }
+
+    pub fn file_input(&self) -> Vec<&str> {
+        self.matches
+            .values_of("file_input")
+            .map_or_else(Vec::new, Iterator::collect)
     }
 
     pub fn ignored_directories(&self) -> Vec<&str> {
@@ -310,7 +519,7 @@ impl Cli {
 
     pub fn print_supported_languages() {
         for (key, extensions) in LanguageType::list() {
-            println!("{} ({})", format!("{}", key), extensions.join(", "));
+            println!("{} ({})", key, extensions.join(", "));
         }
     }
 
@@ -363,7 +572,7 @@ impl Cli {
                 println!(
                     "{:>10} {:<80} {:>12} {:>12} {:>12} {:>12}",
                     l.name(),
-                    e.name.to_string_lossy().to_string(),
+                    e.path().to_string_lossy().to_string(),
                     e.stats.lines(),
                     e.stats.code,
                     e.stats.comments,
@@ -373,7 +582,27 @@ impl Cli {
             _ => None,
         };
 
-        config.types = mem::replace(&mut self.types, None).or(config.types);
+        config.types = self.types.take().or(config.types);
+
+        // Listing, grouping, comparing, and merging files, and their
+        // distribution, need their reports.
+        if self.files
+            || self.distribution
+            || self.top.is_some()
+            || self.by_dir.is_some()
+            || self.group_by.is_some()
+            || self.compare().is_some()
+            || !self.file_input().is_empty()
+        {
+            config.summary_only = Some(false);
+        }
+
+        if !self.path_prefixes.is_empty() {
+            config
+                .path_prefixes
+                .get_or_insert_with(BTreeMap::new)
+                .extend(self.path_prefixes.drain(..));
+        }
 
         config
     }
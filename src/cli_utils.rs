@@ -1,7 +1,9 @@
 use std::{
     borrow::Cow,
+    collections::BTreeMap,
     fmt,
     io::{self, Write},
+    path::Path,
     process,
     str::FromStr,
 };
@@ -10,8 +12,14 @@ use clap::crate_version;
 use colored::Colorize;
 use num_format::ToFormattedString;
 
-use crate::input::Format;
-use tokei::{find_char_boundary, CodeStats, Language, LanguageType, Report};
+use crate::{
+    diff::{Change, LanguageDiff},
+    input::Format,
+};
+use tokei::{
+    find_char_boundary, CodeStats, Config, Distribution, Language, LanguageType, Languages,
+    LineKind, Report,
+};
 
 pub const FALLBACK_ROW_LEN: usize = 79;
 const NO_LANG_HEADER_ROW_LEN: usize = 67;
@@ -50,6 +58,15 @@ pub fn setup_logger(verbose_option: u64) {
     builder.init();
 }
 
+/// Whether languages were found embedded in any of the files of `language`.
+fn has_children(language: &Language) -> bool {
+    !language.children.is_empty()
+        || language
+            .summary
+            .as_ref()
+            .is_some_and(|summary| !summary.stats.blobs.is_empty())
+}
+
 pub fn parse_or_exit<T>(s: &str) -> T
 where
     T: FromStr,
@@ -61,10 +78,55 @@ where
     })
 }
 
+/// Prints every line of `text`, read from the file at `path`, annotated with
+/// what it was counted as, which language counted it, and the rule that
+/// decided it.
+pub fn print_explanation<W: Write>(
+    writer: &mut W,
+    language: LanguageType,
+    path: &Path,
+    text: &[u8],
+    config: &Config,
+) -> io::Result<()> {
+    let lines: Vec<_> = language.classify_file_lines(path, text, config).collect();
+    let number_width = lines.len().to_string().len();
+    let language_name = |line: &tokei::ClassifiedLine| line.child_language().unwrap_or(language);
+    let language_width = lines
+        .iter()
+        .map(|line| language_name(line).name().len())
+        .max()
+        .unwrap_or(0);
+
+    for (number, line) in lines.iter().enumerate() {
+        let reason = line.reason.to_string();
+        let (kind, reason) = match line.kind {
+            LineKind::Blank => ("Blank".dimmed(), reason.dimmed()),
+            LineKind::Code => ("Code".green(), reason.normal()),
+            LineKind::Comment => ("Comment".blue(), reason.normal()),
+        };
+
+        writeln!(
+            writer,
+            "{:>number_width$} {:<8} {:<language_width$} {:<28} {} {}",
+            number + 1,
+            kind,
+            language_name(line).name().bold(),
+            reason,
+            "|".dimmed(),
+            String::from_utf8_lossy(&text[line.range.clone()]),
+            number_width = number_width,
+            language_width = language_width,
+        )?;
+    }
+
+    writer.flush()
+}
+
 #[non_exhaustive]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Default)]
 pub enum NumberFormatStyle {
     // 1234 (Default)
+    #[default]
     Plain,
     // 1,234
     Commas,
@@ -74,12 +136,6 @@ pub enum NumberFormatStyle {
     Underscores,
 }
 
-impl Default for NumberFormatStyle {
-    fn default() -> Self {
-        Self::Plain
-    }
-}
-
 impl FromStr for NumberFormatStyle {
     type Err = String;
 
@@ -149,17 +205,21 @@ impl<W> Printer<W> {
 }
 
 impl<W: Write> Printer<W> {
-    pub fn print_header(&mut self) -> io::Result<()> {
+    pub fn print_header(&mut self, name: &str) -> io::Result<()> {
+        self.print_header_with_columns(name, ["Files", "Lines", "Code", "Comments", "Blanks"])
+    }
+
+    fn print_header_with_columns(&mut self, name: &str, columns: [&str; 5]) -> io::Result<()> {
         self.print_row()?;
         writeln!(
             self.writer,
             " {:<6$} {:>12} {:>12} {:>12} {:>12} {:>12}",
-            "Language".bold().blue(),
-            "Files".bold().blue(),
-            "Lines".bold().blue(),
-            "Code".bold().blue(),
-            "Comments".bold().blue(),
-            "Blanks".bold().blue(),
+            name.bold().blue(),
+            columns[0].bold().blue(),
+            columns[1].bold().blue(),
+            columns[2].bold().blue(),
+            columns[3].bold().blue(),
+            columns[4].bold().blue(),
             self.columns - NO_LANG_HEADER_ROW_LEN
         )?;
         self.print_row()
@@ -182,10 +242,7 @@ impl<W: Write> Printer<W> {
         writeln!(
             self.writer,
             "{:>6} {:>12} {:>12} {:>12} {:>12}",
-            language
-                .reports
-                .len()
-                .to_formatted_string(&self.number_format),
+            language.files().to_formatted_string(&self.number_format),
             language.lines().to_formatted_string(&self.number_format),
             language.code.to_formatted_string(&self.number_format),
             language.comments.to_formatted_string(&self.number_format),
@@ -198,17 +255,20 @@ impl<W: Write> Printer<W> {
         W: Write,
     {
         self.print_language_name(language.inaccurate, "Total", None)?;
+        self.print_total_stats(language)
+    }
+
+    /// Prints the stats of a total made by `Languages::total`, whose files
+    /// are only kept in its children.
+    fn print_total_stats(&mut self, language: &Language) -> io::Result<()> {
         write!(self.writer, " ")?;
         writeln!(
             self.writer,
             "{:>6} {:>12} {:>12} {:>12} {:>12}",
-            language
-                .children
-                .values()
-                .map(Vec::len)
-                .sum::<usize>()
-                .to_formatted_string(&self.number_format)
-                .blue(),
+            (language.children.values().map(Vec::len).sum::<usize>()
+                + language.summary.as_ref().map_or(0, |summary| summary.files))
+            .to_formatted_string(&self.number_format)
+            .blue(),
             language
                 .lines()
                 .to_formatted_string(&self.number_format)
@@ -264,6 +324,7 @@ impl<W: Write> Printer<W> {
     fn print_code_stats(
         &mut self,
         language_type: LanguageType,
+        files: usize,
         stats: &[CodeStats],
     ) -> io::Result<()> {
         self.print_language_name(false, &language_type.to_string(), Some(" |-"))?;
@@ -283,7 +344,7 @@ impl<W: Write> Printer<W> {
             writeln!(
                 self.writer,
                 " {:>6} {:>12} {:>12} {:>12} {:>12}",
-                stats.len().to_formatted_string(&self.number_format),
+                files.to_formatted_string(&self.number_format),
                 (code + comments + blanks).to_formatted_string(&self.number_format),
                 code.to_formatted_string(&self.number_format),
                 comments.to_formatted_string(&self.number_format),
@@ -293,14 +354,22 @@ impl<W: Write> Printer<W> {
     }
 
     fn print_language_total(&mut self, parent: &Language) -> io::Result<()> {
+        let mut children = BTreeMap::<LanguageType, (usize, Vec<CodeStats>)>::new();
         for (language, reports) in &parent.children {
-            self.print_code_stats(
-                *language,
-                &reports
-                    .iter()
-                    .map(|r| r.stats.summarise())
-                    .collect::<Vec<_>>(),
-            )?;
+            let (files, stats) = children.entry(*language).or_default();
+            *files += reports.len();
+            stats.extend(reports.iter().map(|r| r.stats.summarise()));
+        }
+        if let Some(summary) = &parent.summary {
+            for (language, stats) in &summary.stats.blobs {
+                let (files, summarised) = children.entry(*language).or_default();
+                *files += summary.children.get(language).copied().unwrap_or_default();
+                summarised.push(stats.summarise());
+            }
+        }
+
+        for (language, (files, stats)) in children {
+            self.print_code_stats(language, files, &stats)?;
         }
         let mut subtotal = tokei::Report::new("(Total)".into());
         let summary = parent.summarise();
@@ -318,12 +387,12 @@ impl<W: Write> Printer<W> {
     {
         let (a, b): (Vec<_>, Vec<_>) = languages
             .filter(|(_, v)| !v.is_empty())
-            .partition(|(_, l)| compact || l.children.is_empty());
+            .partition(|(_, l)| compact || !has_children(l));
         let mut first = true;
 
         for languages in &[&a, &b] {
             for &(name, language) in *languages {
-                let has_children = !(compact || language.children.is_empty());
+                let has_children = !compact && has_children(language);
                 if first {
                     first = false;
                 } else if has_children || self.list_files {
@@ -354,22 +423,23 @@ impl<W: Write> Printer<W> {
                                     writeln!(self.writer, "{:1$}", report, self.path_length)?;
                                 } else {
                                     if first && a.is_empty() {
-                                        writeln!(self.writer, " {}", report.name.display())?;
+                                        writeln!(self.writer, " {}", report.path().display())?;
                                         first = false;
                                     } else {
                                         writeln!(
                                             self.writer,
                                             "-- {} {}",
-                                            report.name.display(),
+                                            report.path().display(),
                                             "-".repeat(
                                                 self.columns
                                                     - 4
-                                                    - report.name.display().to_string().len()
+                                                    - report.path().display().to_string().len()
                                             )
                                         )?;
                                     }
                                     let mut new_report = (*report).clone();
                                     new_report.name = name.to_string().into();
+                                    new_report.root = None;
                                     writeln!(
                                         self.writer,
                                         " |-{:1$}",
@@ -388,6 +458,201 @@ impl<W: Write> Printer<W> {
         Ok(())
     }
 
+    pub fn print_groups<'a, I>(&mut self, groups: I, compact: bool) -> io::Result<()>
+    where
+        I: Iterator<Item = (&'a str, &'a Language, &'a Languages)>,
+    {
+        let mut first = true;
+
+        for (name, total, languages) in groups {
+            if first {
+                first = false;
+            } else if !compact {
+                self.print_subrow()?;
+            }
+
+            self.print_language_name(total.inaccurate, name, None)?;
+            self.print_total_stats(total)?;
+
+            if compact {
+                continue;
+            }
+
+            for (language_type, language) in languages {
+                let summary = language.summarise();
+                self.print_language_name(language.inaccurate, language_type.name(), Some(" |-"))?;
+                writeln!(
+                    self.writer,
+                    " {:>6} {:>12} {:>12} {:>12} {:>12}",
+                    summary.files().to_formatted_string(&self.number_format),
+                    summary.lines().to_formatted_string(&self.number_format),
+                    summary.code.to_formatted_string(&self.number_format),
+                    summary.comments.to_formatted_string(&self.number_format),
+                    summary.blanks.to_formatted_string(&self.number_format),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Prints how the lines per file are distributed in each language, and in
+    /// all of them.
+    pub fn print_distributions(&mut self, languages: &Languages) -> io::Result<()> {
+        self.print_header_with_columns("Language", ["Files", "Mean", "Median", "P90", "Max"])?;
+
+        for (language_type, language) in languages {
+            if let Some(distribution) = language.distribution() {
+                self.print_language_name(language.inaccurate, language_type.name(), None)?;
+                self.print_distribution(language.reports.len(), &distribution)?;
+            }
+        }
+
+        if let Some(distribution) = languages.distribution() {
+            let files = languages.values().map(|l| l.reports.len()).sum();
+            let inaccurate = languages.values().any(|l| l.inaccurate);
+
+            self.print_row()?;
+            self.print_language_name(inaccurate, "Total", None)?;
+            self.print_distribution(files, &distribution)?;
+        }
+
+        self.print_row()
+    }
+
+    fn print_distribution(&mut self, files: usize, distribution: &Distribution) -> io::Result<()> {
+        writeln!(
+            self.writer,
+            " {:>6} {:>12.1} {:>12} {:>12} {:>12}",
+            files.to_formatted_string(&self.number_format),
+            distribution.mean,
+            distribution.median.to_formatted_string(&self.number_format),
+            distribution.p90.to_formatted_string(&self.number_format),
+            distribution.max.to_formatted_string(&self.number_format),
+        )
+    }
+
+    /// Prints the `n` largest files of all languages, followed by the `n`
+    /// largest files of each language.
+    pub fn print_largest(&mut self, languages: &Languages, n: usize) -> io::Result<()> {
+        self.print_header_with_columns("File", ["", "Lines", "Code", "Comments", "Blanks"])?;
+
+        for (_, report) in languages.largest(n) {
+            self.print_file_total(report)?;
+        }
+
+        for (language_type, language) in languages {
+            if language.reports.is_empty() {
+                continue;
+            }
+
+            self.print_subrow()?;
+            self.print_language_name(language.inaccurate, language_type.name(), None)?;
+            writeln!(self.writer)?;
+            for report in language.largest(n) {
+                self.print_file_total(report)?;
+            }
+        }
+
+        self.print_row()
+    }
+
+    /// Prints a file along with the statistics of the languages embedded in
+    /// it.
+    fn print_file_total(&mut self, report: &Report) -> io::Result<()> {
+        let mut total = report.clone();
+        total.stats = report.stats.summarise();
+
+        writeln!(self.writer, "{:1$}", total, self.path_length)
+    }
+
+    /// Prints the languages that changed between two runs, and with
+    /// `list_files` the files in them that changed, followed by the change in
+    /// the total.
+    pub fn print_diff(&mut self, diffs: &[LanguageDiff]) -> io::Result<()> {
+        let mut first = true;
+
+        for diff in diffs.iter().filter(|diff| !diff.change.is_unchanged()) {
+            if first {
+                first = false;
+            } else if self.list_files {
+                self.print_subrow()?;
+            }
+
+            self.print_change(diff.language.name(), &diff.change, false)?;
+
+            if self.list_files {
+                self.print_subrow()?;
+
+                for (path, change) in &diff.files {
+                    if !change.is_unchanged() {
+                        self.print_change(&path.to_string_lossy(), change, true)?;
+                    }
+                }
+            }
+        }
+
+        self.print_row()?;
+        self.print_change("Total", &crate::diff::total(diffs), false)?;
+        self.print_row()
+    }
+
+    /// Prints a language's change with the file change column, or a file's
+    /// change laid out like the rows of `--files`.
+    fn print_change(&mut self, name: &str, change: &Change, is_file: bool) -> io::Result<()> {
+        let status = if change.is_added() {
+            " (added)".green()
+        } else if change.is_removed() {
+            " (removed)".red()
+        } else {
+            "".normal()
+        };
+        let width = if is_file {
+            self.path_length
+        } else {
+            self.columns - NO_LANG_ROW_LEN
+        };
+        let name_len = width.saturating_sub(status.len()).max(2);
+
+        // truncate and replace the first char with a `|` if the name is too long
+        if name_len < name.len() {
+            let from = find_char_boundary(name, name.len() + 1 - name_len);
+            write!(self.writer, " |{}{}", &name[from..], status)?;
+        } else {
+            let padding = width.saturating_sub(name.len() + status.len());
+            write!(
+                self.writer,
+                " {}{}{}",
+                name.bold(),
+                status,
+                " ".repeat(padding)
+            )?;
+        }
+
+        let [files, lines, code, comments, blanks] = change.deltas();
+        if !is_file {
+            write!(self.writer, " {:>6}", self.format_delta(files))?;
+        }
+        writeln!(
+            self.writer,
+            " {:>12} {:>12} {:>12} {:>12}",
+            self.format_delta(lines),
+            self.format_delta(code),
+            self.format_delta(comments),
+            self.format_delta(blanks),
+        )
+    }
+
+    fn format_delta(&self, delta: i128) -> colored::ColoredString {
+        let formatted = delta.to_formatted_string(&self.number_format);
+
+        match delta.signum() {
+            1 => format!("+{}", formatted).green(),
+            -1 => formatted.red(),
+            _ => formatted.normal(),
+        }
+    }
+
     fn print_row(&mut self) -> io::Result<()> {
         writeln!(self.writer, "{}", self.row)
     }
@@ -436,7 +701,8 @@ impl<W: Write> Printer<W> {
     }
 
     fn print_report_with_name(&mut self, report: &Report) -> io::Result<()> {
-        let name = report.name.to_string_lossy();
+        let path = report.path();
+        let name = path.to_string_lossy();
         let name_length = name.len();
 
         if name_length > self.path_length {
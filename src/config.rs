@@ -1,5 +1,14 @@
-use std::{env, fs, path::PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fs,
+    path::{Path, PathBuf},
+};
 
+use encoding_rs::Encoding;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::de::{self, Deserialize, Deserializer};
+
+use crate::groups::Groups;
 use crate::language::LanguageType;
 use crate::sort::Sort;
 use crate::stats::Report;
@@ -10,14 +19,13 @@ use crate::stats::Report;
 /// ```
 /// use tokei::Config;
 ///
-/// let config = Config {
-///     treat_doc_strings_as_comments: Some(true),
-///     ..Config::default()
-/// };
+/// let mut config = Config::default();
+/// config.treat_doc_strings_as_comments = Some(true);
 /// ```
 ///
 /// [`Languages::get_statistics`]: struct.Languages.html#method.get_statistics
 #[derive(Debug, Default, Deserialize)]
+#[non_exhaustive]
 pub struct Config {
     /// Width of columns to be printed to the terminal. _This option is ignored
     /// in the library._ *Default:* Auto detected width of the terminal.
@@ -45,6 +53,36 @@ pub struct Config {
     /// containing `C`, `Cpp`, and `Rust` with a `Config.types` of `[Cpp, Rust]`
     /// will count only `Cpp` and `Rust`. *Default:* `None`.
     pub types: Option<Vec<LanguageType>>,
+    /// The encodings to decode files with instead of detecting them.
+    /// *Default:* `None`.
+    pub encodings: Option<EncodingOverrides>,
+    /// Top level custom blocks in Vue and Svelte components, such as
+    /// `<i18n>` or `<docs>`, to count as a language, keyed by tag name. A
+    /// block's `lang` attribute takes precedence over the language given
+    /// here. *Default:* `None`.
+    pub custom_blocks: Option<HashMap<String, LanguageType>>,
+    /// Tags of template literals in JavaScript and TypeScript to count as a
+    /// language, such as `md` in `` md`# Title` ``, along with the built in
+    /// tags like `gql` and `css`. *Default:* `None`.
+    pub template_tags: Option<HashMap<String, LanguageType>>,
+    /// Named groups of files to count together, made of `.gitignore` style
+    /// patterns. _This option is ignored in the library, see
+    /// [`Languages::by_group`]._ *Default:* `None`.
+    ///
+    /// [`Languages::by_group`]: struct.Languages.html#method.by_group
+    pub groups: Option<Groups>,
+    /// Prefixes to replace at the start of the paths in reports, mapped to
+    /// what replaces them. An empty replacement strips the prefix. Prefixes
+    /// match whole path components, and the longest matching prefix is
+    /// replaced in each root and each file name. *Default:* `None`.
+    pub path_prefixes: Option<BTreeMap<String, String>>,
+    /// Only total the statistics of each language, without keeping a report
+    /// for each file. Memory use stays the same however many files are
+    /// counted, but there are no files to list, group, or replace when
+    /// merging results, see [`Language::summary`]. *Default:* `false`.
+    ///
+    /// [`Language::summary`]: struct.Language.html#structfield.summary
+    pub summary_only: Option<bool>,
     // /// A map of individual language configuration.
     // pub languages: Option<HashMap<LanguageType, LanguageConfig>>,
     /// Whether to output only the paths for downstream batch processing
@@ -85,6 +123,25 @@ impl Config {
     /// columns = 80
     /// types = ["Python"]
     /// treat_doc_strings_as_comments = true
+    /// summary_only = true
+    ///
+    /// [[encodings]]
+    /// glob = "legacy/*.c"
+    /// encoding = "Shift_JIS"
+    ///
+    /// [custom_blocks]
+    /// i18n = "Json"
+    /// docs = "Markdown"
+    ///
+    /// [template_tags]
+    /// md = "Markdown"
+    ///
+    /// [groups]
+    /// frontend = ["web/**", "ui/**"]
+    /// infra = ["deploy/**", "*.tf"]
+    ///
+    /// [path_prefixes]
+    /// "/home/ci/build" = ""
     // ///
     // /// [[languages.Python]]
     // /// extensions = ["py3"]
@@ -114,6 +171,22 @@ impl Config {
                 .or(conf_dir.treat_doc_strings_as_comments)),
             sort: current_dir.sort.or(home_dir.sort.or(conf_dir.sort)),
             types: current_dir.types.or(home_dir.types.or(conf_dir.types)),
+            encodings: current_dir
+                .encodings
+                .or(home_dir.encodings.or(conf_dir.encodings)),
+            custom_blocks: current_dir
+                .custom_blocks
+                .or(home_dir.custom_blocks.or(conf_dir.custom_blocks)),
+            template_tags: current_dir
+                .template_tags
+                .or(home_dir.template_tags.or(conf_dir.template_tags)),
+            groups: current_dir.groups.or(home_dir.groups.or(conf_dir.groups)),
+            path_prefixes: current_dir
+                .path_prefixes
+                .or(home_dir.path_prefixes.or(conf_dir.path_prefixes)),
+            summary_only: current_dir
+                .summary_only
+                .or(home_dir.summary_only.or(conf_dir.summary_only)),
             for_each_fn: current_dir
                 .for_each_fn
                 .or(home_dir.for_each_fn.or(conf_dir.for_each_fn)),
@@ -134,6 +207,103 @@ impl Config {
     }
 }
 
+/// Encodings to decode files with, chosen by the first glob that matches a
+/// file's path. Globs are matched against the end of the path, so `*.c`
+/// matches C files in any directory and `legacy/*.c` matches them in any
+/// `legacy` directory. Encodings are named by their [WHATWG labels], such as
+/// `"UTF-16LE"`, `"Shift_JIS"`, or `"latin1"`.
+///
+/// ```
+/// use tokei::{Config, EncodingOverrides};
+///
+/// let mut config = Config::default();
+/// config.encodings = Some(EncodingOverrides::new(vec![("legacy/*.c", "Shift_JIS")]).unwrap());
+/// ```
+///
+/// [WHATWG labels]: https://encoding.spec.whatwg.org/#names-and-labels
+#[derive(Clone, Debug)]
+pub struct EncodingOverrides {
+    globs: GlobSet,
+    encodings: Vec<&'static Encoding>,
+    overrides: Vec<(String, String)>,
+}
+
+impl EncodingOverrides {
+    /// Creates overrides from pairs of globs and encoding labels. Returns an
+    /// error if a glob is invalid or an encoding isn't recognised.
+    pub fn new<I, G, E>(overrides: I) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = (G, E)>,
+        G: AsRef<str>,
+        E: AsRef<str>,
+    {
+        let mut globs = GlobSetBuilder::new();
+        let mut encodings = Vec::new();
+        let mut sources = Vec::new();
+
+        for (glob, label) in overrides {
+            let (glob, label) = (glob.as_ref(), label.as_ref());
+            sources.push((glob.to_owned(), label.to_owned()));
+            let pattern = if glob.starts_with("**/") {
+                glob.to_owned()
+            } else {
+                format!("**/{}", glob.trim_start_matches('/'))
+            };
+            let glob = GlobBuilder::new(&pattern)
+                .literal_separator(true)
+                .build()
+                .map_err(|e| format!("Invalid encoding glob: {}", e))?;
+            let encoding = Encoding::for_label(label.as_bytes())
+                .ok_or_else(|| format!("Unsupported encoding: {}", label))?;
+
+            globs.add(glob);
+            encodings.push(encoding);
+        }
+
+        Ok(Self {
+            globs: globs
+                .build()
+                .map_err(|e| format!("Invalid encoding glob: {}", e))?,
+            encodings,
+            overrides: sources,
+        })
+    }
+
+    /// The globs and encoding labels the overrides were created from, in
+    /// order.
+    pub fn overrides(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.overrides
+            .iter()
+            .map(|(glob, label)| (&**glob, &**label))
+    }
+
+    /// The encoding for the file at `path`, if any glob matches it.
+    pub(crate) fn encoding_for(&self, path: &Path) -> Option<&'static Encoding> {
+        self.globs
+            .matches(path)
+            .into_iter()
+            .min()
+            .map(|i| self.encodings[i])
+    }
+}
+
+impl<'de> Deserialize<'de> for EncodingOverrides {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct EncodingOverride {
+            glob: String,
+            encoding: String,
+        }
+
+        let overrides = Vec::<EncodingOverride>::deserialize(deserializer)?;
+
+        Self::new(overrides.into_iter().map(|o| (o.glob, o.encoding))).map_err(de::Error::custom)
+    }
+}
+
 /*
 /// Configuration for a individual [`LanguageType`].
 ///
@@ -0,0 +1,200 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use tokei::{Language, LanguageType, Report};
+
+use crate::input::LanguageMap;
+
+/// The totals that are compared between two runs.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Totals {
+    pub files: usize,
+    pub lines: usize,
+    pub code: usize,
+    pub comments: usize,
+    pub blanks: usize,
+}
+
+impl Totals {
+    /// The totals of a language, including the languages embedded in it.
+    fn of_language(language: &Language) -> Self {
+        let summary = language.summarise();
+
+        Self {
+            files: language.files(),
+            lines: summary.lines(),
+            code: summary.code,
+            comments: summary.comments,
+            blanks: summary.blanks,
+        }
+    }
+
+    /// The totals of a file, including the languages embedded in it.
+    fn of_report(report: &Report) -> Self {
+        let summary = report.stats.summarise();
+
+        Self {
+            files: 1,
+            lines: summary.lines(),
+            code: summary.code,
+            comments: summary.comments,
+            blanks: summary.blanks,
+        }
+    }
+}
+
+impl std::ops::AddAssign for Totals {
+    fn add_assign(&mut self, rhs: Self) {
+        self.files += rhs.files;
+        self.lines += rhs.lines;
+        self.code += rhs.code;
+        self.comments += rhs.comments;
+        self.blanks += rhs.blanks;
+    }
+}
+
+/// The totals of something in the old and the new run, which is `None` in
+/// the run it's missing from.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Change {
+    pub old: Option<Totals>,
+    pub new: Option<Totals>,
+}
+
+impl Change {
+    pub fn is_added(&self) -> bool {
+        self.old.is_none() && self.new.is_some()
+    }
+
+    pub fn is_removed(&self) -> bool {
+        self.old.is_some() && self.new.is_none()
+    }
+
+    pub fn is_unchanged(&self) -> bool {
+        self.old == self.new
+    }
+
+    /// The new totals minus the old totals, as files, lines, code, comments,
+    /// and blanks.
+    pub fn deltas(&self) -> [i128; 5] {
+        let old = self.old.unwrap_or_default();
+        let new = self.new.unwrap_or_default();
+        let delta = |old: usize, new: usize| new as i128 - old as i128;
+
+        [
+            delta(old.files, new.files),
+            delta(old.lines, new.lines),
+            delta(old.code, new.code),
+            delta(old.comments, new.comments),
+            delta(old.blanks, new.blanks),
+        ]
+    }
+}
+
+/// How a language changed between two runs, along with its files.
+#[derive(Debug)]
+pub struct LanguageDiff {
+    pub language: LanguageType,
+    pub change: Change,
+    pub files: BTreeMap<PathBuf, Change>,
+}
+
+/// Compares the results of two runs, language by language and file by file.
+/// Files are matched by their paths, including their roots.
+pub fn diff(old: &LanguageMap, new: &LanguageMap) -> Vec<LanguageDiff> {
+    let mut languages: Vec<_> = old.keys().chain(new.keys()).copied().collect();
+    languages.sort();
+    languages.dedup();
+
+    languages
+        .into_iter()
+        .map(|language| {
+            let (old, new) = (old.get(&language), new.get(&language));
+            let mut files = BTreeMap::<PathBuf, Change>::new();
+
+            for report in old.into_iter().flat_map(|l| &l.reports) {
+                let change = files.entry(report.path()).or_default();
+                *change.old.get_or_insert_with(Totals::default) += Totals::of_report(report);
+            }
+
+            for report in new.into_iter().flat_map(|l| &l.reports) {
+                let change = files.entry(report.path()).or_default();
+                *change.new.get_or_insert_with(Totals::default) += Totals::of_report(report);
+            }
+
+            LanguageDiff {
+                language,
+                change: Change {
+                    old: old.map(Totals::of_language),
+                    new: new.map(Totals::of_language),
+                },
+                files,
+            }
+        })
+        .collect()
+}
+
+/// The change in the totals of every language.
+pub fn total(diffs: &[LanguageDiff]) -> Change {
+    let mut old = Totals::default();
+    let mut new = Totals::default();
+
+    for diff in diffs {
+        old += diff.change.old.unwrap_or_default();
+        new += diff.change.new.unwrap_or_default();
+    }
+
+    Change {
+        old: Some(old),
+        new: Some(new),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn language(reports: &[(&str, usize)]) -> Language {
+        let mut language = Language::new();
+        for &(name, code) in reports {
+            let mut report = Report::new(name.into());
+            report.stats.code = code;
+            language.add_report(report);
+        }
+        language.total();
+        language
+    }
+
+    #[test]
+    fn languages_and_files_are_compared() {
+        let mut old = LanguageMap::new();
+        old.insert(
+            LanguageType::Rust,
+            language(&[("main.rs", 10), ("old.rs", 5)]),
+        );
+        old.insert(LanguageType::C, language(&[("main.c", 3)]));
+        let mut new = LanguageMap::new();
+        new.insert(
+            LanguageType::Rust,
+            language(&[("main.rs", 12), ("new.rs", 1)]),
+        );
+        new.insert(LanguageType::Python, language(&[("main.py", 2)]));
+
+        let diffs = diff(&old, &new);
+        let languages: Vec<_> = diffs.iter().map(|d| d.language).collect();
+        assert_eq!(
+            languages,
+            [LanguageType::C, LanguageType::Python, LanguageType::Rust]
+        );
+
+        assert!(diffs[0].change.is_removed());
+        assert!(diffs[1].change.is_added());
+
+        let rust = &diffs[2];
+        assert_eq!(rust.change.deltas(), [0, -2, -2, 0, 0]);
+        assert_eq!(rust.files[&PathBuf::from("main.rs")].deltas()[2], 2);
+        assert!(rust.files[&PathBuf::from("new.rs")].is_added());
+        assert!(rust.files[&PathBuf::from("old.rs")].is_removed());
+
+        assert_eq!(total(&diffs).deltas(), [0, -3, -3, 0, 0]);
+    }
+}
@@ -0,0 +1,91 @@
+use std::{collections::BTreeMap, path::Path};
+
+use globset::GlobSet;
+use serde::de::{self, Deserialize, Deserializer};
+
+use crate::utils::path::{normalise, pattern_globs};
+
+/// Named groups of files, each made of `.gitignore` style patterns relative
+/// to the directory tokei is run from. A pattern containing a `/` other than
+/// at its end only matches from that directory, otherwise it matches at any
+/// depth, and a pattern matching a directory covers everything in it. A file
+/// belongs to every group that has a pattern matching it.
+///
+/// ```
+/// use tokei::{Config, Groups};
+///
+/// let groups = Groups::new(vec![
+///     ("frontend", vec!["web/**", "ui/**"]),
+///     ("infra", vec!["deploy/**", "*.tf"]),
+/// ])
+/// .unwrap();
+///
+/// assert_eq!(groups.groups_of("web/index.js".as_ref()), ["frontend"]);
+/// assert_eq!(groups.groups_of("./modules/vpc/main.tf".as_ref()), ["infra"]);
+/// assert!(groups.groups_of("src/main.rs".as_ref()).is_empty());
+///
+/// let mut config = Config::default();
+/// config.groups = Some(groups);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Groups {
+    groups: Vec<(String, Vec<GlobSet>)>,
+}
+
+impl Groups {
+    /// The name files that aren't in any group are grouped under.
+    pub const UNGROUPED: &'static str = "ungrouped";
+
+    /// Creates groups from pairs of names and patterns. Returns an error if a
+    /// pattern is empty or invalid.
+    pub fn new<I, N, P, S>(groups: I) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = (N, P)>,
+        N: Into<String>,
+        P: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let groups = groups
+            .into_iter()
+            .map(|(name, patterns)| {
+                let name = name.into();
+                let globs = patterns
+                    .into_iter()
+                    .map(|pattern| {
+                        pattern_globs(pattern.as_ref()).ok_or_else(|| {
+                            format!("Invalid pattern in group {}: {:?}", name, pattern.as_ref())
+                        })
+                    })
+                    .collect::<Result<_, _>>()?;
+
+                Ok((name, globs))
+            })
+            .collect::<Result<_, String>>()?;
+
+        Ok(Self { groups })
+    }
+
+    /// The names of the groups the file at `path` is in, where `path` is
+    /// relative to the directory the patterns are relative to.
+    #[must_use]
+    pub fn groups_of(&self, path: &Path) -> Vec<&str> {
+        let path = normalise(path);
+
+        self.groups
+            .iter()
+            .filter(|(_, globs)| globs.iter().any(|globs| globs.is_match(&path)))
+            .map(|(name, _)| &**name)
+            .collect()
+    }
+}
+
+impl<'de> Deserialize<'de> for Groups {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let groups = BTreeMap::<String, Vec<String>>::deserialize(deserializer)?;
+
+        Self::new(groups).map_err(de::Error::custom)
+    }
+}
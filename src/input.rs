@@ -1,16 +1,307 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::{collections::BTreeMap, error::Error, str::FromStr};
-
-use tokei::{Language, LanguageType, Languages};
-
-type LanguageMap = BTreeMap<LanguageType, Language>;
-
-#[derive(Deserialize, Serialize, Debug)]
-struct Output {
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tokei::{CodeOwners, Config, Distribution, Groups, Language, LanguageType, Languages, Report};
+
+pub type LanguageMap = BTreeMap<LanguageType, Language>;
+
+/// The version of the envelope written with `--metadata`. Envelopes with a
+/// newer version than this aren't read.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Deserialize, Serialize, Debug, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct Output {
     #[serde(flatten)]
     languages: LanguageMap,
     #[serde(rename = "Total")]
     totals: Language,
+    #[serde(
+        rename = "Directories",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    directories: Option<BTreeMap<PathBuf, Languages>>,
+    #[serde(rename = "Owners", default, skip_serializing_if = "Option::is_none")]
+    owners: Option<BTreeMap<String, Languages>>,
+    #[serde(rename = "Groups", default, skip_serializing_if = "Option::is_none")]
+    groups: Option<BTreeMap<String, Languages>>,
+    #[serde(
+        rename = "Distribution",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    distribution: Option<Distributions>,
+    #[serde(rename = "Largest", default, skip_serializing_if = "Option::is_none")]
+    largest: Option<LargestFiles>,
+}
+
+impl Output {
+    pub fn new(languages: &Languages) -> Self {
+        Self {
+            languages: (*languages).to_owned(),
+            totals: languages.total(),
+            directories: None,
+            owners: None,
+            groups: None,
+            distribution: None,
+            largest: None,
+        }
+    }
+
+    /// Adds the statistics of each directory, see `Languages::by_directory`.
+    pub fn with_directories(mut self, languages: &Languages, depth: usize) -> Self {
+        self.directories = Some(languages.by_directory(depth));
+        self
+    }
+
+    /// Adds the statistics of each owner, see `Languages::by_owner`.
+    pub fn with_owners(mut self, languages: &Languages, owners: &CodeOwners) -> Self {
+        self.owners = Some(languages.by_owner(owners));
+        self
+    }
+
+    /// Adds the statistics of each group, see `Languages::by_group`.
+    pub fn with_groups(mut self, languages: &Languages, groups: &Groups) -> Self {
+        self.groups = Some(languages.by_group(groups));
+        self
+    }
+
+    /// Adds how the lines per file are distributed, see
+    /// `Languages::distribution`.
+    pub fn with_distribution(mut self, languages: &Languages) -> Self {
+        self.distribution = Some(Distributions {
+            languages: languages
+                .iter()
+                .filter_map(|(ty, language)| Some((*ty, language.distribution()?)))
+                .collect(),
+            total: languages.distribution(),
+        });
+        self
+    }
+
+    /// Adds the `n` largest files, see `Languages::largest`.
+    pub fn with_largest(mut self, languages: &Languages, n: usize) -> Self {
+        let file = |language, report: &Report| LargestFile {
+            language,
+            path: report.path(),
+            lines: report.stats.summarise().lines(),
+        };
+
+        self.largest = Some(LargestFiles {
+            languages: languages
+                .iter()
+                .filter(|(_, language)| !language.reports.is_empty())
+                .map(|(ty, language)| {
+                    let largest = language.largest(n);
+                    (*ty, largest.into_iter().map(|r| file(*ty, r)).collect())
+                })
+                .collect(),
+            total: languages
+                .largest(n)
+                .into_iter()
+                .map(|(ty, report)| file(ty, report))
+                .collect(),
+        });
+        self
+    }
+}
+
+/// How the lines per file are distributed in each language, and in all of
+/// them.
+#[derive(Deserialize, Serialize, Debug, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct Distributions {
+    languages: BTreeMap<LanguageType, Distribution>,
+    total: Option<Distribution>,
+}
+
+/// The largest files of each language, and of all of them.
+#[derive(Deserialize, Serialize, Debug, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct LargestFiles {
+    languages: BTreeMap<LanguageType, Vec<LargestFile>>,
+    total: Vec<LargestFile>,
+}
+
+/// One of the largest files.
+#[derive(Deserialize, Serialize, Debug, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct LargestFile {
+    language: LanguageType,
+    path: PathBuf,
+    /// The lines of the file, including the lines of the languages embedded
+    /// in it.
+    lines: usize,
+}
+
+/// Results along with the metadata of the run that produced them.
+#[derive(Deserialize, Serialize, Debug, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct Envelope {
+    schema_version: u32,
+    metadata: Metadata,
+    results: Output,
+}
+
+impl Envelope {
+    pub fn new(metadata: Metadata, results: Output) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            metadata,
+            results,
+        }
+    }
+}
+
+/// What produced a set of results. Every field is optional when reading, so
+/// envelopes missing some of them can still be read.
+#[derive(Deserialize, Serialize, Debug, Default, PartialEq, JsonSchema)]
+#[serde(default)]
+#[schemars(deny_unknown_fields)]
+pub struct Metadata {
+    tokei_version: String,
+    /// Seconds since the Unix epoch.
+    timestamp: u64,
+    paths: Vec<String>,
+    excluded: Vec<String>,
+    config: RunConfig,
+    git_commit: Option<String>,
+}
+
+/// The options in `Config` that change what is counted.
+#[derive(Deserialize, Serialize, Debug, Default, PartialEq, JsonSchema)]
+#[serde(default)]
+#[schemars(deny_unknown_fields)]
+struct RunConfig {
+    hidden: Option<bool>,
+    no_ignore: Option<bool>,
+    no_ignore_parent: Option<bool>,
+    no_ignore_dot: Option<bool>,
+    no_ignore_vcs: Option<bool>,
+    treat_doc_strings_as_comments: Option<bool>,
+    types: Option<Vec<LanguageType>>,
+    encodings: Option<Vec<EncodingOverride>>,
+    custom_blocks: Option<BTreeMap<String, LanguageType>>,
+    template_tags: Option<BTreeMap<String, LanguageType>>,
+    path_prefixes: Option<BTreeMap<String, String>>,
+    summary_only: Option<bool>,
+}
+
+/// An encoding to decode the files matching a glob with, as written in the
+/// config files.
+#[derive(Deserialize, Serialize, Debug, PartialEq, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+struct EncodingOverride {
+    glob: String,
+    encoding: String,
+}
+
+impl Metadata {
+    /// The metadata of counting `paths` now, excluding `excluded`.
+    pub fn new(paths: &[&str], excluded: &[&str], config: &Config) -> Self {
+        Self {
+            tokei_version: clap::crate_version!().to_owned(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs()),
+            paths: paths.iter().map(|&path| path.to_owned()).collect(),
+            excluded: excluded.iter().map(|&path| path.to_owned()).collect(),
+            config: RunConfig {
+                hidden: config.hidden,
+                no_ignore: config.no_ignore,
+                no_ignore_parent: config.no_ignore_parent,
+                no_ignore_dot: config.no_ignore_dot,
+                no_ignore_vcs: config.no_ignore_vcs,
+                treat_doc_strings_as_comments: config.treat_doc_strings_as_comments,
+                types: config.types.clone(),
+                encodings: config.encodings.as_ref().map(|encodings| {
+                    encodings
+                        .overrides()
+                        .map(|(glob, encoding)| EncodingOverride {
+                            glob: glob.to_owned(),
+                            encoding: encoding.to_owned(),
+                        })
+                        .collect()
+                }),
+                custom_blocks: config
+                    .custom_blocks
+                    .as_ref()
+                    .map(|blocks| blocks.clone().into_iter().collect()),
+                template_tags: config
+                    .template_tags
+                    .as_ref()
+                    .map(|tags| tags.clone().into_iter().collect()),
+                path_prefixes: config.path_prefixes.clone(),
+                summary_only: config.summary_only,
+            },
+            git_commit: paths.first().and_then(|path| git_commit(Path::new(path))),
+        }
+    }
+}
+
+/// The commit checked out in the repository containing `path`, if it's in
+/// a git repository and git is installed.
+fn git_commit(path: &Path) -> Option<String> {
+    let directory = if path.is_dir() {
+        path
+    } else {
+        path.parent()
+            .filter(|parent| !parent.as_os_str().is_empty())?
+    };
+
+    let output = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .current_dir(directory)
+        .output()
+        .ok()?;
+
+    let commit = String::from_utf8(output.stdout).ok()?;
+    let commit = commit.trim();
+
+    if output.status.success() && !commit.is_empty() {
+        Some(commit.to_owned())
+    } else {
+        None
+    }
+}
+
+/// Either an envelope, or results from before envelopes or written without
+/// `--metadata`.
+#[allow(clippy::large_enum_variant)]
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged)]
+#[schemars(
+    title = "tokei output",
+    extend("$id" = "https://github.com/XAMPPRocky/tokei/blob/master/tokei.schema.json")
+)]
+pub enum Input {
+    Envelope(Envelope),
+    Output(Output),
+}
+
+impl Input {
+    fn into_languages(self) -> Option<LanguageMap> {
+        match self {
+            Input::Envelope(envelope) if envelope.schema_version > SCHEMA_VERSION => {
+                warn!(
+                    "Results were written by a newer version of tokei, with schema version {}",
+                    envelope.schema_version
+                );
+                None
+            }
+            Input::Envelope(envelope) => Some(envelope.results.languages),
+            Input::Output(output) => Some(output.languages),
+        }
+    }
 }
 
 macro_rules! supported_formats {
@@ -73,8 +364,8 @@ macro_rules! supported_formats {
                     return None
                 }
 
-                if let Ok(Output { languages, .. }) = serde_json::from_str::<Output>(input) {
-                    return Some(languages);
+                if let Ok(input) = serde_json::from_str::<Input>(input) {
+                    return input.into_languages();
                 }
 
                 $(
@@ -83,8 +374,8 @@ macro_rules! supported_formats {
                     {
                         let parse = &{ $parse_kode };
 
-                        if let Ok(Output { languages, .. }) = parse(input) {
-                            return Some(languages)
+                        if let Ok(input) = parse(input) {
+                            return Input::into_languages(input)
                         }
                     }
                 )+
@@ -93,18 +384,13 @@ macro_rules! supported_formats {
                 None
             }
 
-            pub fn print(&self, languages: &Languages) -> Result<String, Box<dyn Error>> {
-                let output = Output {
-                    languages: (*languages).to_owned(),
-                    totals: languages.total()
-                };
-
+            pub fn print<T: Serialize>(&self, output: &T) -> Result<String, Box<dyn Error>> {
                 match *self {
-                    Format::Json => Ok(serde_json::to_string(&output)?),
+                    Format::Json => Ok(serde_json::to_string(output)?),
                     $(
                         #[cfg(feature = $feature)] Format::$variant => {
                             let print= &{ $print_kode };
-                            Ok(print(&output)?)
+                            Ok(print(output)?)
                         }
                     ),+
                 }
@@ -165,10 +451,21 @@ supported_formats!(
 );
 
 pub fn add_input(input: &str, languages: &mut Languages) -> bool {
+    if let Some(map) = read_input(input) {
+        *languages += map;
+        true
+    } else {
+        false
+    }
+}
+
+/// Reads the results of a previous tokei run from a file path, "stdin", or
+/// the results themselves.
+pub fn read_input(input: &str) -> Option<LanguageMap> {
     use std::fs::File;
     use std::io::Read;
 
-    let map = match File::open(input) {
+    match File::open(input) {
         Ok(mut file) => {
             let contents = {
                 let mut contents = String::new();
@@ -190,13 +487,6 @@ pub fn add_input(input: &str, languages: &mut Languages) -> bool {
                 convert_input(input)
             }
         }
-    };
-
-    if let Some(map) = map {
-        *languages += map;
-        true
-    } else {
-        false
     }
 }
 
@@ -222,12 +512,62 @@ mod tests {
 
         // Check that the value matches after serializing and deserializing
         for variant in Format::iter() {
+            let outputs = [
+                Output::new(&langs),
+                Output::new(&langs).with_directories(&langs, 1),
+            ];
+
+            for output in &outputs {
+                let serialized = variant
+                    .print(output)
+                    .unwrap_or_else(|_| panic!("Failed serializing variant: {:?}", variant));
+                let deserialized = Format::parse(&serialized)
+                    .unwrap_or_else(|| panic!("Failed deserializing variant: {:?}", variant));
+                assert_eq!(*langs, deserialized);
+            }
+
+            let envelope = Envelope::new(
+                Metadata::new(&["tests/data"], &[], &Config::default()),
+                Output::new(&langs),
+            );
             let serialized = variant
-                .print(&langs)
-                .expect(&format!("Failed serializing variant: {:?}", variant));
+                .print(&envelope)
+                .unwrap_or_else(|_| panic!("Failed serializing envelope: {:?}", variant));
             let deserialized = Format::parse(&serialized)
-                .expect(&format!("Failed deserializing variant: {:?}", variant));
+                .unwrap_or_else(|| panic!("Failed deserializing envelope: {:?}", variant));
             assert_eq!(*langs, deserialized);
         }
     }
+
+    #[test]
+    fn envelopes_from_newer_versions_are_not_read() {
+        let data_dir = Path::new("tests").join("data");
+        let mut langs = Languages::new();
+        langs.get_statistics(&[data_dir], &[], &Config::default());
+
+        let mut envelope = Envelope::new(Metadata::default(), Output::new(&langs));
+        envelope.schema_version = SCHEMA_VERSION + 1;
+
+        assert!(Format::parse(&Format::Json.print(&envelope).unwrap()).is_none());
+    }
+
+    #[test]
+    fn directories_are_serialized_by_depth() {
+        let data_dir = Path::new("tests").join("data");
+        let mut langs = Languages::new();
+        langs.get_statistics(&[&data_dir], &[], &Config::default());
+
+        let serialized = Format::Json
+            .print(&Output::new(&langs).with_directories(&langs, 2))
+            .unwrap();
+        let output: Output = serde_json::from_str(&serialized).unwrap();
+        let directories = output.directories.unwrap();
+
+        assert_eq!(directories.keys().collect::<Vec<_>>(), [&data_dir]);
+        assert_eq!(directories[&data_dir].total().code, langs.total().code);
+
+        let serialized = Format::Json.print(&Output::new(&langs)).unwrap();
+        let output: Output = serde_json::from_str(&serialized).unwrap();
+        assert!(output.directories.is_none());
+    }
 }
@@ -0,0 +1,298 @@
+use std::{fmt, iter::FromIterator, ops::Range};
+
+use rayon::prelude::*;
+
+use grep_searcher::{LineIter, LineStep};
+
+use crate::{stats::CodeStats, LanguageType};
+
+/// What a single line was counted as.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum LineKind {
+    /// A line containing only whitespace.
+    Blank,
+    /// A line of code.
+    Code,
+    /// A line of comments.
+    Comment,
+}
+
+/// Why a line was classified the way it was.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum LineReason {
+    /// The line only contains whitespace.
+    Blank,
+    /// The line contains none of the language's important syntax, so it was
+    /// classified by looking only at how it starts.
+    FastPath,
+    /// The language is literate, so every non-blank line is a comment.
+    Literate,
+    /// The line starts with a line comment, or is entirely one multi-line
+    /// comment.
+    WholeLineComment,
+    /// The line is part of a multi-line comment, nested `depth` comments deep.
+    MultiLineComment {
+        /// How many multi-line comments were open.
+        depth: usize,
+    },
+    /// The line is part of a doc string that is being treated as comments.
+    DocString,
+    /// The line ends inside a string literal.
+    String,
+    /// The line opens a block of an embedded language, such as a Markdown code
+    /// fence or a `<script>` tag.
+    EmbeddedLanguage(LanguageType),
+    /// None of the other rules applied, so the line is code.
+    Code,
+}
+
+impl fmt::Display for LineReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LineReason::Blank => f.write_str("blank"),
+            LineReason::FastPath => f.write_str("fast path"),
+            LineReason::Literate => f.write_str("literate"),
+            LineReason::WholeLineComment => f.write_str("whole line comment"),
+            LineReason::MultiLineComment { depth } => {
+                write!(f, "multi-line comment (depth {})", depth)
+            }
+            LineReason::DocString => f.write_str("doc string"),
+            LineReason::String => f.write_str("string"),
+            LineReason::EmbeddedLanguage(language) => write!(f, "opens {}", language),
+            LineReason::Code => f.write_str("code"),
+        }
+    }
+}
+
+/// A single line of text along with how tokei counted it.
+///
+/// ```
+/// use tokei::{Config, LanguageType, LineKind};
+///
+/// let text = b"// Hello\nfn main() {}\n";
+/// let lines: Vec<_> = LanguageType::Rust
+///     .classify_lines(text, &Config::default())
+///     .collect();
+///
+/// assert_eq!(lines[0].kind, LineKind::Comment);
+/// assert_eq!(lines[1].kind, LineKind::Code);
+/// assert_eq!(&text[lines[1].range.clone()], b"fn main() {}");
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct ClassifiedLine {
+    /// The byte range of the line in the classified text, excluding its line
+    /// terminator.
+    pub range: Range<usize>,
+    /// What the line was counted as.
+    pub kind: LineKind,
+    /// The rule that decided `kind`.
+    pub reason: LineReason,
+    /// The embedded languages the line was counted under, outermost first.
+    /// Empty when the line belongs to the language being classified.
+    pub embedding: Vec<LanguageType>,
+}
+
+impl ClassifiedLine {
+    /// The innermost embedded language the line was counted under, if any.
+    #[must_use]
+    pub fn child_language(&self) -> Option<LanguageType> {
+        self.embedding.last().copied()
+    }
+}
+
+impl FromIterator<ClassifiedLine> for CodeStats {
+    fn from_iter<I: IntoIterator<Item = ClassifiedLine>>(iter: I) -> Self {
+        let mut stats = CodeStats::new();
+
+        for line in iter {
+            let stats = line.embedding.iter().fold(&mut stats, |stats, language| {
+                stats.blobs.entry(*language).or_default()
+            });
+            stats.push_line(line.range, line.kind, line.reason);
+        }
+
+        stats
+    }
+}
+
+/// A destination for classified lines. `CodeStats` only counts them, while a
+/// `Vec<ClassifiedLine>` keeps every line, which means the totals and the
+/// per-line classification always come from the same state machine.
+pub(crate) trait Classification: Default + Send {
+    /// Records a line in the current language.
+    fn push_line(&mut self, range: Range<usize>, kind: LineKind, reason: LineReason);
+
+    /// Records every line of a child language found in the current language,
+    /// where `offset` is the position of the child's text in the parent's.
+    fn push_child(&mut self, language: LanguageType, child: Self, offset: usize);
+
+    /// Records all the lines of `other`, which must come after the lines
+    /// already recorded.
+    fn append(&mut self, other: Self);
+
+    /// Classifies every line in `text` using `classify`, which is only given
+    /// lines that don't contain any important syntax.
+    fn simple_lines<F>(text: &[u8], classify: F) -> Self
+    where
+        F: Fn(&[u8]) -> (LineKind, LineReason) + Sync,
+    {
+        let mut classification = Self::default();
+        let mut stepper = LineStep::new(b'\n', 0, text.len());
+
+        while let Some((start, end)) = stepper.next(text) {
+            let (kind, reason) = classify(&text[start..end]);
+            classification.push_line(line_range(text, start, end), kind, reason);
+        }
+
+        classification
+    }
+}
+
+impl Classification for CodeStats {
+    fn push_line(&mut self, _: Range<usize>, kind: LineKind, _: LineReason) {
+        match kind {
+            LineKind::Blank => self.blanks += 1,
+            LineKind::Code => self.code += 1,
+            LineKind::Comment => self.comments += 1,
+        }
+    }
+
+    fn push_child(&mut self, language: LanguageType, child: Self, _: usize) {
+        *self.blobs.entry(language).or_default() += child;
+    }
+
+    fn append(&mut self, other: Self) {
+        *self += other;
+    }
+
+    fn simple_lines<F>(text: &[u8], classify: F) -> Self
+    where
+        F: Fn(&[u8]) -> (LineKind, LineReason) + Sync,
+    {
+        let (blanks, code, comments) = LineIter::new(b'\n', text)
+            .par_bridge()
+            .map(|line| match classify(line).0 {
+                LineKind::Blank => (1, 0, 0),
+                LineKind::Code => (0, 1, 0),
+                LineKind::Comment => (0, 0, 1),
+            })
+            .reduce(|| (0, 0, 0), |a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2));
+
+        CodeStats {
+            blanks,
+            code,
+            comments,
+            ..CodeStats::new()
+        }
+    }
+}
+
+impl Classification for Vec<ClassifiedLine> {
+    fn push_line(&mut self, range: Range<usize>, kind: LineKind, reason: LineReason) {
+        self.push(ClassifiedLine {
+            range,
+            kind,
+            reason,
+            embedding: Vec::new(),
+        });
+    }
+
+    fn push_child(&mut self, language: LanguageType, child: Self, offset: usize) {
+        self.extend(child.into_iter().map(|mut line| {
+            line.range = (line.range.start + offset)..(line.range.end + offset);
+            line.embedding.insert(0, language);
+            line
+        }));
+    }
+
+    fn append(&mut self, other: Self) {
+        self.extend(other);
+    }
+}
+
+/// The range of the line between `start` and `end` without its line
+/// terminator.
+pub(crate) fn line_range(text: &[u8], start: usize, mut end: usize) -> Range<usize> {
+    if end > start && text[end - 1] == b'\n' {
+        end -= 1;
+    }
+
+    if end > start && text[end - 1] == b'\r' {
+        end -= 1;
+    }
+
+    start..end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Config;
+
+    #[test]
+    fn classification_matches_totals() {
+        let text =
+            b"//! Docs\n//! ```rust\n//! let x = 1;\n//! ```\n\nfn main() {\n    /* a */\n}\n";
+        let config = Config::default();
+
+        let stats: CodeStats = LanguageType::Rust.classify_lines(text, &config).collect();
+
+        assert_eq!(stats, LanguageType::Rust.parse_from_slice(text, &config));
+    }
+
+    #[test]
+    fn embedded_lines_keep_their_position() {
+        let text = b"<p>hi</p>\n<script>\nlet x = 5;\n</script>\n";
+        let lines: Vec<_> = LanguageType::Html
+            .classify_lines(text, &Config::default())
+            .collect();
+
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[2].child_language(), Some(LanguageType::JavaScript));
+        assert_eq!(lines[2].kind, LineKind::Code);
+        assert_eq!(&text[lines[2].range.clone()], b"let x = 5;");
+        assert_eq!(
+            lines[1].reason,
+            LineReason::EmbeddedLanguage(LanguageType::JavaScript)
+        );
+        assert_eq!(lines[3].child_language(), None);
+    }
+
+    #[test]
+    fn reasons_track_comment_depth() {
+        let text = b"fn main() {\n/* a /* b\n c */\n*/\n}\n";
+        let reasons: Vec<_> = LanguageType::Rust
+            .classify_lines(text, &Config::default())
+            .map(|line| line.reason)
+            .collect();
+
+        assert_eq!(
+            reasons,
+            [
+                LineReason::FastPath,
+                LineReason::MultiLineComment { depth: 2 },
+                LineReason::MultiLineComment { depth: 2 },
+                LineReason::MultiLineComment { depth: 1 },
+                LineReason::FastPath,
+            ]
+        );
+    }
+
+    #[test]
+    fn doc_comments_map_back_to_the_source() {
+        let text = b"/// # Title\n///\n/// Body\nfn f() {}\n";
+        let lines: Vec<_> = LanguageType::Rust
+            .classify_lines(text, &Config::default())
+            .collect();
+
+        assert_eq!(lines.len(), 4);
+        assert_eq!(&text[lines[0].range.clone()], b"/// # Title");
+        assert_eq!(lines[1].kind, LineKind::Blank);
+        assert_eq!(lines[1].child_language(), Some(LanguageType::Markdown));
+        assert_eq!(&text[lines[2].range.clone()], b"/// Body");
+        assert_eq!(lines[3].kind, LineKind::Code);
+    }
+}
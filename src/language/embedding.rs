@@ -1,199 +1,904 @@
-#![allow(clippy::trivial_regex)]
+use std::{ops::Range, path::Path, str::FromStr};
 
-use crate::LanguageType;
-use once_cell::sync::Lazy;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use memchr::{memchr, memchr_iter};
 use regex::bytes::Regex;
 
-pub static START_SCRIPT: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r#"<script(?:.*type="(.*)")?.*?>"#).unwrap());
-pub static END_SCRIPT: Lazy<Regex> = Lazy::new(|| Regex::new(r#"</script>"#).unwrap());
+use super::classify::LineKind;
+use crate::{utils::ext::SliceExt, Config, LanguageType};
 
-pub static START_STYLE: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r#"<style(?:.*lang="(.*)")?.*?>"#).unwrap());
-pub static END_STYLE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"</style>"#).unwrap());
-
-pub static START_TEMPLATE: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r#"<template(?:.*lang="(.*)")?.*?>"#).unwrap());
-pub static END_TEMPLATE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"</template>"#).unwrap());
-
-pub static STARTING_MARKDOWN_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r#"```\S+\s"#).unwrap());
-pub static ENDING_MARKDOWN_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r#"```\s?"#).unwrap());
-
-/// A memory of a regex matched.
-/// The values provided by `Self::start` and `Self::end` are in the same space as the
-/// start value supplied to `RegexCache::build`
-pub struct Capture<'a> {
-    start: usize,
-    text: &'a [u8],
+/// A rule for finding another language embedded in a host language, as
+/// declared under `embeddings` in `languages.json`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum EmbeddingRule {
+    /// A block of the child language between the lines matching `start` and
+    /// a line matching `end`. The host counts the lines matching `start` as
+    /// `delimiters`, unless `opening_line_is_child` is set, and `closing_line`
+    /// decides who counts the line matching `end`.
+    Block {
+        /// Text that always appears in a match of `start`, so lines without
+        /// any can be skipped.
+        triggers: &'static [&'static str],
+        start: &'static str,
+        /// How many lines `start` is matched against, for blocks like
+        /// AsciiDoc listings that are opened by more than one line.
+        start_lines: usize,
+        /// The pattern matching the line the block ends on, or `None` if the
+        /// block ends before the first line that is indented no further than
+        /// the line it starts on.
+        end: Option<&'static str>,
+        /// How the first capture group of `start` names the child language.
+        capture: Option<LanguageCapture>,
+        /// The child language when `start` doesn't name a known one.
+        default: Option<LanguageType>,
+        delimiters: LineKind,
+        /// Whether a block without an end runs to the end of the file, rather
+        /// than not being a block at all.
+        runs_to_end_of_file: bool,
+        /// Whether the block can only open on the first line of the file,
+        /// like front matter.
+        only_at_start_of_file: bool,
+        /// Whether the text after `start` on the line it matches is the
+        /// first line of the child, like the command of a Dockerfile `RUN`.
+        opening_line_is_child: bool,
+        closing_line: ClosingLine,
+        /// Text starting each line of the block that isn't part of the child
+        /// language, like the `*` starting each line of a doc comment.
+        line_prefix: Option<&'static str>,
+        /// If the block is a doc string, whether it's only counted as a child
+        /// language when doc strings are treated as comments, or only when
+        /// they aren't.
+        doc_strings_as_comments: Option<bool>,
+        /// Globs matching the paths of the files the block is found in, at
+        /// any depth, or any file if there are none.
+        paths: &'static [&'static str],
+        /// A pattern the first non-blank line of the block has to match for
+        /// it to be counted, like the first item of a YAML sequence.
+        first_line: Option<&'static str>,
+    },
+    /// A run of lines that each start with one of `prefixes`, counted as
+    /// `language` once the prefixes are blanked out. If `is_documentation`
+    /// is set, the lines are documentation written in `language`, so they're
+    /// counted as comments rather than as its code.
+    Prefix {
+        prefixes: &'static [&'static str],
+        language: LanguageType,
+        is_documentation: bool,
+    },
+    /// A code block between fences following the CommonMark rules, where
+    /// `fences` are the shortest fences allowed. The block is counted as the
+    /// language named by its info string, and the host counts both fences as
+    /// `delimiters`.
+    Fence {
+        fences: &'static [&'static str],
+        delimiters: LineKind,
+    },
+    /// An element like `<script>` or `<style>`, which ends at its closing
+    /// tag. The element is counted as the language named by its `lang` or
+    /// `type` attribute, or else `default`, and the host counts its start
+    /// tag as `delimiters`. Without a `tag`, the element is any top level
+    /// custom block named in [`Config::custom_blocks`].
+    ///
+    /// [`Config::custom_blocks`]: crate::Config::custom_blocks
+    Element {
+        tag: Option<&'static str>,
+        triggers: &'static [&'static str],
+        default: Option<LanguageType>,
+        delimiters: LineKind,
+    },
+    /// A tagged template literal, like `` gql`query { … }` ``, counted as the
+    /// language its tag names in `tags` or in [`Config::template_tags`]. The
+    /// host counts the lines opening and closing the template as
+    /// `delimiters`.
+    ///
+    /// [`Config::template_tags`]: crate::Config::template_tags
+    TaggedTemplate {
+        tags: &'static [(&'static str, LanguageType)],
+        delimiters: LineKind,
+    },
 }
 
-impl Capture<'_> {
-    #[inline(always)]
-    fn start(&self) -> usize {
-        self.start
-    }
-    #[inline(always)]
-    pub fn end(&self) -> usize {
-        self.start + self.text.len()
+impl EmbeddingRule {
+    /// The text that has to appear on a line for the rule to match it.
+    pub(crate) fn triggers(&self) -> &'static [&'static str] {
+        match *self {
+            EmbeddingRule::Block { triggers, .. } => triggers,
+            EmbeddingRule::Prefix { prefixes, .. } => prefixes,
+            EmbeddingRule::Fence { fences, .. } => fences,
+            EmbeddingRule::Element { triggers, .. } => triggers,
+            EmbeddingRule::TaggedTemplate { .. } => &["`"],
+        }
     }
-    #[inline(always)]
-    pub fn as_bytes(&self) -> &[u8] {
-        self.text
-    }
-}
 
-impl<'a> std::fmt::Debug for Capture<'a> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Capture")
-            .field("start", &self.start)
-            .field("end", &self.end())
-            .field("text", &String::from_utf8_lossy(self.text))
-            .finish()
+    /// The language the rule's blocks are counted as when they don't name
+    /// one.
+    pub(crate) fn default_language(&self) -> Option<LanguageType> {
+        match *self {
+            EmbeddingRule::Block { default, .. } | EmbeddingRule::Element { default, .. } => {
+                default
+            }
+            EmbeddingRule::Prefix { language, .. } => Some(language),
+            EmbeddingRule::Fence { .. } | EmbeddingRule::TaggedTemplate { .. } => None,
+        }
     }
 }
 
-pub(crate) struct RegexCache<'a> {
-    inner: Option<RegexFamily<'a>>,
+/// Who counts the line a block ends on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ClosingLine {
+    /// The host, with its own syntax.
+    Host,
+    /// The host, as the block's `delimiters`, for blocks whose end the host
+    /// wouldn't recognise.
+    Delimiters,
+    /// The child, as its last line.
+    Child,
 }
 
-/// Embedding regexes are similar between different sets of languages.
-/// `RegexFamily` records both which family the language belongs to,
-/// as well as the actual matches
-pub(crate) enum RegexFamily<'a> {
-    HtmlLike(HtmlLike<'a>),
-    Markdown(Markdown<'a>),
-    Rust,
+/// How the text captured by an embedding rule names a language.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum LanguageCapture {
+    /// A MIME type, such as `text/javascript`.
+    Mime,
+    /// A comma separated list of names or file extensions, the first known
+    /// one being used.
+    Name,
 }
 
-pub(crate) struct HtmlLike<'a> {
-    start_script: Option<Box<[Capture<'a>]>>,
-    start_style: Option<Box<[Capture<'a>]>>,
-    start_template: Option<Box<[Capture<'a>]>>,
+/// An [`EmbeddingRule`] with its patterns compiled.
+#[derive(Clone, Debug)]
+pub(crate) enum Embedding {
+    Block {
+        triggers: &'static [&'static str],
+        start: Regex,
+        start_lines: usize,
+        end: Option<Regex>,
+        capture: Option<LanguageCapture>,
+        default: Option<LanguageType>,
+        delimiters: LineKind,
+        runs_to_end_of_file: bool,
+        only_at_start_of_file: bool,
+        opening_line_is_child: bool,
+        closing_line: ClosingLine,
+        line_prefix: Option<&'static str>,
+        doc_strings_as_comments: Option<bool>,
+        paths: Option<GlobSet>,
+        first_line: Option<Regex>,
+    },
+    Prefix {
+        prefixes: &'static [&'static str],
+        language: LanguageType,
+        is_documentation: bool,
+    },
+    Fence {
+        fences: &'static [&'static str],
+        delimiters: LineKind,
+    },
+    Element {
+        tag: Option<&'static str>,
+        default: Option<LanguageType>,
+        delimiters: LineKind,
+    },
+    TaggedTemplate {
+        tags: &'static [(&'static str, LanguageType)],
+        delimiters: LineKind,
+    },
 }
 
-pub(crate) struct Markdown<'a> {
-    starts: Option<Box<[Capture<'a>]>>,
-}
+impl Embedding {
+    pub(crate) fn compile(rule: EmbeddingRule) -> Self {
+        let compile = |pattern| {
+            Regex::new(pattern).unwrap_or_else(|e| panic!("Invalid embedding pattern: {}", e))
+        };
 
-impl<'a> HtmlLike<'a> {
-    pub fn start_script_in_range(
-        &'a self,
-        start: usize,
-        end: usize,
-    ) -> Option<impl Iterator<Item = &'a Capture<'a>>> {
-        filter_range(self.start_script.as_ref()?, start, end)
+        match rule {
+            EmbeddingRule::Block {
+                triggers,
+                start,
+                start_lines,
+                end,
+                capture,
+                default,
+                delimiters,
+                runs_to_end_of_file,
+                only_at_start_of_file,
+                opening_line_is_child,
+                closing_line,
+                line_prefix,
+                doc_strings_as_comments,
+                paths,
+                first_line,
+            } => Embedding::Block {
+                triggers,
+                start: compile(start),
+                start_lines,
+                end: end.map(compile),
+                capture,
+                default,
+                delimiters,
+                runs_to_end_of_file,
+                only_at_start_of_file,
+                opening_line_is_child,
+                closing_line,
+                line_prefix,
+                doc_strings_as_comments,
+                paths: compile_paths(paths),
+                first_line: first_line.map(compile),
+            },
+            EmbeddingRule::Prefix {
+                prefixes,
+                language,
+                is_documentation,
+            } => Embedding::Prefix {
+                prefixes,
+                language,
+                is_documentation,
+            },
+            EmbeddingRule::Fence { fences, delimiters } => Embedding::Fence { fences, delimiters },
+            EmbeddingRule::Element {
+                tag,
+                default,
+                delimiters,
+                ..
+            } => Embedding::Element {
+                tag,
+                default,
+                delimiters,
+            },
+            EmbeddingRule::TaggedTemplate { tags, delimiters } => {
+                Embedding::TaggedTemplate { tags, delimiters }
+            }
+        }
     }
+}
 
-    pub fn start_style_in_range(
-        &'a self,
-        start: usize,
-        end: usize,
-    ) -> Option<impl Iterator<Item = &'a Capture<'a>>> {
-        filter_range(self.start_style.as_ref()?, start, end)
+/// Builds the globs matching `paths` at any depth, or `None` if there are no
+/// paths to match.
+fn compile_paths(paths: &[&str]) -> Option<GlobSet> {
+    if paths.is_empty() {
+        return None;
     }
 
-    pub fn start_template_in_range(
-        &'a self,
-        start: usize,
-        end: usize,
-    ) -> Option<impl Iterator<Item = &'a Capture<'a>>> {
-        filter_range(self.start_template.as_ref()?, start, end)
+    let mut globs = GlobSetBuilder::new();
+    for path in paths {
+        let glob = GlobBuilder::new(&format!("**/{}", path))
+            .literal_separator(true)
+            .build()
+            .unwrap_or_else(|e| panic!("Invalid embedding path: {}", e));
+        globs.add(glob);
     }
+
+    Some(
+        globs
+            .build()
+            .unwrap_or_else(|e| panic!("Invalid embedding path: {}", e)),
+    )
 }
 
-impl<'a> Markdown<'a> {
-    pub fn starts_in_range(&'a self, start: usize, end: usize) -> Option<&Capture<'a>> {
-        filter_range(self.starts.as_ref()?, start, end).and_then(|mut it| it.next())
-    }
+/// Where a block rule's `start` pattern matched on a line.
+#[derive(Clone, Debug)]
+pub(crate) struct BlockMatch {
+    /// The index of the rule in the language's embeddings.
+    pub(crate) rule: usize,
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    /// The text naming the child language, if the rule captured any.
+    pub(crate) capture: Option<Range<usize>>,
 }
 
-fn filter_range<'a>(
-    dataset: &'a [Capture<'a>],
+/// Finds where each of the block rules in `embedding` first match on the
+/// line `lines[start..end]`, which is the first line of the file if
+/// `at_start_of_file` is set. Rules limited to some paths only match in a
+/// file at one of them. Returns `None` if a rule needs more lines than
+/// `lines` has, unless `eof` is set.
+pub(crate) fn match_line(
+    embedding: &[Embedding],
+    lines: &[u8],
     start: usize,
     end: usize,
-) -> Option<impl Iterator<Item = &'a Capture<'a>>> {
-    let pos = dataset
-        .binary_search_by_key(&start, |cap| cap.start())
-        .ok()?;
-
-    if pos >= dataset.len() || dataset[pos].end() > end {
-        None
-    } else {
-        Some(
-            dataset[pos..]
-                .iter()
-                .take_while(move |cap| cap.end() <= end),
-        )
-    }
-}
-
-impl<'a> RegexCache<'a> {
-    /// Returns the language family for which regexes were matched, if any
-    pub(crate) fn family(&self) -> Option<&RegexFamily> {
-        self.inner.as_ref()
-    }
-
-    /// Tries to memoize any matches of embedding regexes that occur within lines[start..end]
-    /// for the given language. Any `Capture` values eventually recovered will use the same
-    /// zero for their start as the given `start` argument.
-    pub(crate) fn build(lang: LanguageType, lines: &'a [u8], start: usize, end: usize) -> Self {
-        let inner = match lang {
-            LanguageType::Markdown | LanguageType::UnrealDeveloperMarkdown => {
-                let markdown = Markdown {
-                    starts: save_captures(&STARTING_MARKDOWN_REGEX, lines, start, end),
-                };
-
-                if markdown.starts.is_some() {
-                    Some(RegexFamily::Markdown(markdown))
-                } else {
-                    None
+    path: Option<&Path>,
+    at_start_of_file: bool,
+    eof: bool,
+) -> Option<Vec<BlockMatch>> {
+    let mut matches = Vec::new();
+
+    for (rule, embedding) in embedding.iter().enumerate() {
+        let (triggers, pattern, start_lines) = match embedding {
+            Embedding::Block {
+                only_at_start_of_file: true,
+                ..
+            } if !at_start_of_file => continue,
+            Embedding::Block {
+                paths: Some(paths), ..
+            } if !path.is_some_and(|path| paths.is_match(path)) => continue,
+            Embedding::Block {
+                triggers,
+                start,
+                start_lines,
+                ..
+            } => (triggers, start, *start_lines),
+            Embedding::Element { tag, .. } => {
+                if let Some((start, end)) = match_element(*tag, lines, start, end, eof)? {
+                    matches.push(BlockMatch {
+                        rule,
+                        start,
+                        end,
+                        capture: None,
+                    });
                 }
+                continue;
             }
-            LanguageType::Rust => Some(RegexFamily::Rust),
-            LanguageType::Html
-            | LanguageType::RubyHtml
-            | LanguageType::Svelte
-            | LanguageType::Vue => {
-                let html = HtmlLike {
-                    start_script: save_captures(&START_SCRIPT, lines, start, end),
-                    start_style: save_captures(&START_STYLE, lines, start, end),
-                    start_template: save_captures(&START_TEMPLATE, lines, start, end),
-                };
-
-                if html.start_script.is_some()
-                    || html.start_style.is_some()
-                    || html.start_template.is_some()
-                {
-                    Some(RegexFamily::HtmlLike(html))
-                } else {
-                    None
+            // Any backtick can start a template, so every tagged one is kept.
+            Embedding::TaggedTemplate { .. } => {
+                for position in memchr_iter(b'`', &lines[start..end]).map(|i| start + i) {
+                    let tag_start = template_tag_start(&lines[start..position]);
+
+                    if tag_start < position - start {
+                        matches.push(BlockMatch {
+                            rule,
+                            start: position,
+                            end: position + 1,
+                            capture: Some((start + tag_start)..position),
+                        });
+                    }
                 }
+                continue;
             }
-            _ => None,
+            Embedding::Prefix { .. } | Embedding::Fence { .. } => continue,
         };
-        Self { inner }
+
+        let mut window_end = end;
+        for _ in 1..start_lines {
+            match memchr(b'\n', &lines[window_end..]) {
+                Some(i) => window_end += i + 1,
+                None if eof => window_end = lines.len(),
+                None => {
+                    let line = &lines[start..end];
+                    if triggers.iter().any(|t| line.contains_slice(t.as_bytes())) {
+                        return None;
+                    }
+                    window_end = lines.len();
+                }
+            }
+        }
+
+        let captures = match pattern.captures(&lines[start..window_end]) {
+            Some(captures) => captures,
+            None => continue,
+        };
+        let whole = captures.get(0).unwrap();
+
+        if start + whole.start() < end {
+            matches.push(BlockMatch {
+                rule,
+                start: start + whole.start(),
+                end: start + whole.end(),
+                capture: captures
+                    .get(1)
+                    .map(|m| (start + m.start())..(start + m.end())),
+            });
+        }
     }
+
+    Some(matches)
 }
 
-fn save_captures<'a>(
-    regex: &Regex,
-    lines: &'a [u8],
+/// Finds the first start tag of an element named `tag` on the line
+/// `lines[start..end]`, or of any element at the start of the line if there's
+/// no `tag`, returning where the tag starts and ends. Returns `None` if the
+/// tag may continue past the end of `lines`, unless `eof` is set.
+fn match_element(
+    tag: Option<&str>,
+    lines: &[u8],
     start: usize,
     end: usize,
-) -> Option<Box<[Capture<'a>]>> {
-    let v: Vec<_> = regex
-        .captures(&lines[start..end])?
+    eof: bool,
+) -> Option<Option<(usize, usize)>> {
+    let positions = memchr_iter(b'<', &lines[start..end])
+        .map(|i| start + i)
+        // Custom blocks are top level elements, so they have to start the line.
+        .take_while(|&position| tag.is_some() || position == start);
+
+    for position in positions {
+        let name = match StartTag::name(&lines[position..]) {
+            Some(name) => name,
+            None => continue,
+        };
+        if tag.is_some_and(|tag| !name.eq_ignore_ascii_case(tag.as_bytes())) {
+            continue;
+        }
+
+        match StartTag::parse(&lines[position..]) {
+            Some(StartTag {
+                end: Some(tag_end),
+                self_closing: false,
+                ..
+            }) => {
+                return Some(Some((position, position + tag_end)));
+            }
+            Some(StartTag { end: None, .. }) if !eof => return None,
+            _ => {}
+        }
+    }
+
+    Some(None)
+}
+
+/// An HTML start tag, like `<script setup lang="ts">`, which may span more
+/// than one line.
+#[derive(Clone, Debug)]
+pub(crate) struct StartTag<'a> {
+    pub(crate) name: &'a [u8],
+    attributes: Vec<(&'a [u8], &'a [u8])>,
+    /// The position just after the tag's `>`, or `None` if the text ends
+    /// before it.
+    pub(crate) end: Option<usize>,
+    self_closing: bool,
+}
+
+impl<'a> StartTag<'a> {
+    /// The name of the start tag `text` begins with, if any.
+    fn name(text: &'a [u8]) -> Option<&'a [u8]> {
+        let rest = text.strip_prefix(b"<")?;
+        if !rest.first()?.is_ascii_alphabetic() {
+            return None;
+        }
+        let len = rest
+            .iter()
+            .position(|&c| c.is_ascii_whitespace() || c == b'/' || c == b'>')
+            .unwrap_or(rest.len());
+
+        Some(&rest[..len])
+    }
+
+    /// Parses the start tag `text` begins with, if any.
+    pub(crate) fn parse(text: &'a [u8]) -> Option<Self> {
+        let name = Self::name(text)?;
+        let mut tag = StartTag {
+            name,
+            attributes: Vec::new(),
+            end: None,
+            self_closing: false,
+        };
+        let mut i = 1 + name.len();
+        let skip_whitespace = |mut i: usize| {
+            while text.get(i).is_some_and(u8::is_ascii_whitespace) {
+                i += 1;
+            }
+            i
+        };
+
+        loop {
+            i = skip_whitespace(i);
+            match text.get(i) {
+                None => return Some(tag),
+                Some(b'>') => break,
+                Some(b'/') if text.get(i + 1) == Some(&b'>') => {
+                    tag.self_closing = true;
+                    i += 1;
+                    break;
+                }
+                Some(b'/') => {
+                    i += 1;
+                    continue;
+                }
+                Some(_) => {}
+            }
+
+            let name_start = i;
+            while text
+                .get(i)
+                .is_some_and(|&c| !c.is_ascii_whitespace() && !b"=>/".contains(&c))
+            {
+                i += 1;
+            }
+            let name = &text[name_start..i];
+
+            let after_name = skip_whitespace(i);
+            if text.get(after_name) != Some(&b'=') {
+                tag.attributes.push((name, &[]));
+                continue;
+            }
+            i = skip_whitespace(after_name + 1);
+
+            let value = match text.get(i) {
+                None => return Some(tag),
+                Some(&quote) if quote == b'"' || quote == b'\'' => {
+                    let value_start = i + 1;
+                    let len = match memchr(quote, &text[value_start..]) {
+                        Some(len) => len,
+                        None => return Some(tag),
+                    };
+                    i = value_start + len + 1;
+                    &text[value_start..value_start + len]
+                }
+                Some(_) => {
+                    let value_start = i;
+                    while text
+                        .get(i)
+                        .is_some_and(|&c| !c.is_ascii_whitespace() && c != b'>')
+                    {
+                        i += 1;
+                    }
+                    &text[value_start..i]
+                }
+            };
+            tag.attributes.push((name, value));
+        }
+
+        tag.end = Some(i + 1);
+        Some(tag)
+    }
+
+    /// The value of the attribute called `name`, if the tag has one.
+    fn attribute(&self, name: &str) -> Option<&'a [u8]> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| attribute.eq_ignore_ascii_case(name.as_bytes()))
+            .map(|&(_, value)| value)
+    }
+
+    /// The language named by the tag's `lang` attribute, or else by its
+    /// `type` attribute.
+    pub(crate) fn language(&self) -> Option<LanguageType> {
+        let lang = self
+            .attribute("lang")
+            .and_then(|lang| child_language(Some(lang), Some(LanguageCapture::Name), None));
+        if lang.is_some() {
+            return lang;
+        }
+
+        let kind = self.attribute("type")?.trim().to_ascii_lowercase();
+        match &*kind {
+            b"module" => Some(LanguageType::JavaScript),
+            b"importmap" | b"speculationrules" => Some(LanguageType::Json),
+            kind if kind.contains(&b'/') => {
+                child_language(Some(kind), Some(LanguageCapture::Mime), None)
+            }
+            kind => child_language(Some(kind), Some(LanguageCapture::Name), None),
+        }
+    }
+}
+
+/// Finds the closing tag of the element named `name` in `lines`, starting
+/// from `start`, returning where the closing tag starts and ends. Elements
+/// other than `<script>` and `<style>` can contain elements of the same name,
+/// which have to be closed first.
+pub(crate) fn find_closing_tag(lines: &[u8], start: usize, name: &[u8]) -> Option<(usize, usize)> {
+    let nests = !name.eq_ignore_ascii_case(b"script") && !name.eq_ignore_ascii_case(b"style");
+    let mut depth = 0usize;
+
+    for position in memchr_iter(b'<', &lines[start..]).map(|i| start + i) {
+        let text = &lines[position..];
+
+        if let Some(rest) = text.strip_prefix(b"</") {
+            let len = name.len().min(rest.len());
+            let rest_of_tag = &rest[len..];
+            let closes = rest[..len].eq_ignore_ascii_case(name)
+                && rest_of_tag.trim_start().starts_with(b">");
+
+            if closes && depth == 0 {
+                let tag_len = 2 + len + rest_of_tag.len() - rest_of_tag.trim_start().len() + 1;
+                return Some((position, position + tag_len));
+            } else if closes {
+                depth -= 1;
+            }
+        } else if nests
+            && StartTag::name(text).is_some_and(|tag| tag.eq_ignore_ascii_case(name))
+            && !StartTag::parse(text).is_some_and(|tag| tag.self_closing)
+        {
+            depth += 1;
+        }
+    }
+
+    None
+}
+
+fn is_identifier(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c == b'$'
+}
+
+/// Where the tag of a template literal starting at the end of `before`
+/// starts, such as `styled.div` or `styled(Button)`. This is the end of
+/// `before` if the template has no tag.
+fn template_tag_start(before: &[u8]) -> usize {
+    let mut start = before.len();
+
+    while start > 0 {
+        match before[start - 1] {
+            c if is_identifier(c) || c == b'.' => start -= 1,
+            b')' => {
+                let mut depth = 0;
+                let open = before[..start].iter().rposition(|&c| {
+                    depth += usize::from(c == b')');
+                    depth -= usize::from(c == b'(');
+                    depth == 0
+                });
+
+                match open {
+                    Some(open) => start = open,
+                    None => break,
+                }
+            }
+            _ => break,
+        }
+    }
+
+    // Tags are expressions, so they can't start in the middle of one.
+    match before.get(start) {
+        Some(&c) if is_identifier(c) && !c.is_ascii_digit() => start,
+        _ => before.len(),
+    }
+}
+
+/// The language the tag of a template literal names. Tags like `Prisma.sql`
+/// are named by their last identifier, and tags like `styled.div` or
+/// `styled(Button)` by their first, with the tags in `config` taking
+/// precedence over those in `tags`.
+pub(crate) fn template_language(
+    tag: &[u8],
+    tags: &[(&str, LanguageType)],
+    config: &Config,
+) -> Option<LanguageType> {
+    let last_len = tag.iter().rev().take_while(|&&c| is_identifier(c)).count();
+    let first_len = tag.iter().take_while(|&&c| is_identifier(c)).count();
+    let names = [&tag[tag.len() - last_len..], &tag[..first_len]];
+
+    names
         .iter()
-        .flatten()
-        .map(|cap| Capture {
-            start: start + cap.start(),
-            text: cap.as_bytes(),
+        .filter(|name| !name.is_empty())
+        .map(|name| String::from_utf8_lossy(name))
+        .find_map(|name| {
+            config
+                .template_tags
+                .as_ref()
+                .and_then(|tags| tags.get(&*name).copied())
+                .or_else(|| tags.iter().find(|(tag, _)| *tag == name).map(|&(_, l)| l))
         })
-        .collect();
+}
+
+/// Finds the backtick closing the template literal whose text starts at
+/// `start` in `lines`, skipping over any nested in its `${}` substitutions.
+pub(crate) fn find_template_end(lines: &[u8], start: usize) -> Option<usize> {
+    // Each entry is a template, or the depth of braces in a substitution.
+    let mut stack = vec![None];
+    let mut quote = None;
+    let mut i = start;
+
+    while i < lines.len() {
+        let c = lines[i];
+
+        match (*stack.last()?, quote) {
+            (_, Some(quote_char)) => {
+                if c == b'\\' {
+                    i += 1;
+                } else if c == quote_char {
+                    quote = None;
+                }
+            }
+            (None, None) => match c {
+                b'\\' => i += 1,
+                b'`' => {
+                    stack.pop();
+                    if stack.is_empty() {
+                        return Some(i);
+                    }
+                }
+                b'$' if lines.get(i + 1) == Some(&b'{') => {
+                    stack.push(Some(0));
+                    i += 1;
+                }
+                _ => {}
+            },
+            (Some(depth), None) => match c {
+                b'`' => stack.push(None),
+                b'"' | b'\'' => quote = Some(c),
+                b'{' => *stack.last_mut().unwrap() = Some(depth + 1),
+                b'}' if depth == 0 => {
+                    stack.pop();
+                }
+                b'}' => *stack.last_mut().unwrap() = Some(depth - 1),
+                _ => {}
+            },
+        }
+
+        i += 1;
+    }
+
+    None
+}
+
+/// An opening code fence, following the CommonMark rules.
+#[derive(Clone, Debug)]
+pub(crate) struct Fence {
+    indent: usize,
+    marker: u8,
+    len: usize,
+    /// The language named by the fence's info string, if any.
+    pub(crate) language: Option<LanguageType>,
+}
+
+impl Fence {
+    /// The fence opened by `line`, if any, where `fences` are the shortest
+    /// fences allowed. Any indentation is allowed, as fences can be nested
+    /// inside list items.
+    pub(crate) fn open(line: &[u8], fences: &[&str]) -> Option<Self> {
+        let indent = line.len() - line.trim_start().len();
+        let rest = &line[indent..];
+        let marker = *rest.first()?;
+        let shortest = fences
+            .iter()
+            .find(|fence| fence.as_bytes()[0] == marker)?
+            .len();
+        let len = rest.iter().take_while(|&&c| c == marker).count();
+        let info = rest[len..].trim();
+
+        // Backticks in the info string would make it inline code instead.
+        if len < shortest || (marker == b'`' && info.contains(&b'`')) {
+            return None;
+        }
+
+        Some(Self {
+            indent,
+            marker,
+            len,
+            language: info_language(info),
+        })
+    }
+
+    /// Whether `line` closes the fence, by being a fence of the same marker
+    /// that is at least as long and has no info string.
+    pub(crate) fn is_closed_by(&self, line: &[u8]) -> bool {
+        let indent = line.len() - line.trim_start().len();
+        let rest = &line[indent..];
+        let len = rest.iter().take_while(|&&c| c == self.marker).count();
+
+        indent <= self.indent + 3 && len >= self.len && rest[len..].trim().is_empty()
+    }
+}
+
+/// The language named by a fence's info string, such as `rust,ignore`,
+/// `python title="example.py"`, or `{.haskell .numberLines}`.
+fn info_language(info: &[u8]) -> Option<LanguageType> {
+    let info = match info.strip_prefix(b"{") {
+        Some(attributes) => attributes.split(|&c| c == b'}').next().unwrap_or_default(),
+        None => info,
+    };
+    let word = info
+        .split(|c| c.is_ascii_whitespace())
+        .find(|word| !word.is_empty())?;
+    let word = word.strip_prefix(b".").unwrap_or(word);
+
+    child_language(Some(word), Some(LanguageCapture::Name), None)
+}
+
+/// The language named by `captured`, or `default` if it doesn't name a known
+/// language.
+pub(crate) fn child_language(
+    captured: Option<&[u8]>,
+    capture: Option<LanguageCapture>,
+    default: Option<LanguageType>,
+) -> Option<LanguageType> {
+    let named = captured
+        .map(|text| String::from_utf8_lossy(text.trim()).into_owned())
+        .and_then(|text| match capture? {
+            LanguageCapture::Mime => LanguageType::from_mime(&text),
+            LanguageCapture::Name => text.split(',').find_map(|name| {
+                let name = name.trim();
+                LanguageType::from_str(name)
+                    .ok()
+                    .or_else(|| LanguageType::from_file_extension(&name.to_lowercase()))
+            }),
+        });
+
+    named.or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FENCES: &[&str] = &["```", "~~~"];
+
+    #[test]
+    fn fences_name_languages_in_info_strings() {
+        let language = |line: &[u8]| Fence::open(line, FENCES).and_then(|f| f.language);
+
+        assert_eq!(language(b"```rust,ignore\n"), Some(LanguageType::Rust));
+        assert_eq!(
+            language(b"~~~ {.python .numberLines}\n"),
+            Some(LanguageType::Python)
+        );
+        assert_eq!(
+            language(b"```toml title=\"Cargo.toml\"\n"),
+            Some(LanguageType::Toml)
+        );
+        assert_eq!(language(b"```\n"), None);
+        assert!(Fence::open(b"``rust\n", FENCES).is_none());
+        assert!(Fence::open(b"``` `rust`\n", FENCES).is_none());
+    }
+
+    #[test]
+    fn fences_close_on_matching_markers() {
+        let fence = Fence::open(b"  ````md\n", FENCES).unwrap();
+
+        assert!(fence.is_closed_by(b"`````\n"));
+        assert!(fence.is_closed_by(b"     ````  \n"));
+        assert!(!fence.is_closed_by(b"```\n"));
+        assert!(!fence.is_closed_by(b"~~~~\n"));
+        assert!(!fence.is_closed_by(b"```` rust\n"));
+        assert!(!fence.is_closed_by(b"      ````\n"));
+    }
+
+    #[test]
+    fn start_tags_name_languages_in_attributes() {
+        let language = |text: &[u8]| StartTag::parse(text).and_then(|tag| tag.language());
+
+        assert_eq!(
+            language(b"<script setup lang=\"ts\">"),
+            Some(LanguageType::TypeScript)
+        );
+        assert_eq!(
+            language(b"<script\n  type=module\n  src='a.js'>"),
+            Some(LanguageType::JavaScript)
+        );
+        assert_eq!(
+            language(b"<script type='text/x-python' data-x=\"a > b\">"),
+            Some(LanguageType::Python)
+        );
+        assert_eq!(language(b"<i18n lang=\"json\">"), Some(LanguageType::Json));
+        assert_eq!(language(b"<style scoped>"), None);
+        assert_eq!(StartTag::parse(b"<style lang=\"scss").unwrap().end, None);
+        assert!(StartTag::parse(b"< b").is_none());
+    }
+
+    #[test]
+    fn template_tags_name_languages() {
+        let config = Config {
+            template_tags: Some(
+                vec![("md".to_owned(), LanguageType::Markdown)]
+                    .into_iter()
+                    .collect(),
+            ),
+            ..Config::default()
+        };
+        let tags = &[
+            ("css", LanguageType::Css),
+            ("sql", LanguageType::Sql),
+            ("styled", LanguageType::Css),
+        ];
+        let language = |before: &[u8]| {
+            let tag = &before[template_tag_start(before)..];
+            template_language(tag, tags, &config)
+        };
+
+        assert_eq!(language(b"const a = styled.div"), Some(LanguageType::Css));
+        assert_eq!(
+            language(b"styled(Button).attrs({ x: 1 })"),
+            Some(LanguageType::Css)
+        );
+        assert_eq!(language(b"await Prisma.sql"), Some(LanguageType::Sql));
+        assert_eq!(language(b"return md"), Some(LanguageType::Markdown));
+        assert_eq!(language(b"const a = notcss"), None);
+        assert_eq!(template_tag_start(b"f(x, "), 5);
+    }
+
+    #[test]
+    fn templates_end_after_substitutions() {
+        let text = b"a ${b ? `c}` : '`'} d\\` e` f";
+
+        assert_eq!(find_template_end(text, 0), Some(25));
+        assert_eq!(find_template_end(b"a ${`b`", 0), None);
+    }
+
+    #[test]
+    fn closing_tags_skip_nested_elements() {
+        let text = b"<template>\n<template v-if=\"a\"></template>\n</template >\n";
+        let (start, end) = find_closing_tag(text, 10, b"template").unwrap();
+        assert_eq!(&text[start..end], b"</template >");
 
-    if v.is_empty() {
-        None
-    } else {
-        Some(v.into())
+        let text = b"<script>\nx = '<script>';\n</script>\n";
+        let (start, _) = find_closing_tag(text, 9, b"script").unwrap();
+        assert_eq!(start, 25);
     }
 }
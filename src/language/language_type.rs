@@ -5,46 +5,196 @@ use std::{
     io::{self, BufRead, BufReader, Read},
     path::{Path, PathBuf},
     str::FromStr,
+    sync::Arc,
 };
 
 use crate::{
     config::Config,
-    language::syntax::{FileContext, LanguageContext, SyntaxCounter},
+    language::{
+        classify::{line_range, Classification, ClassifiedLine, LineKind, LineReason},
+        embedding::{self, ClosingLine, EmbeddingRule, LanguageCapture},
+        syntax::{AnalysisReport, FileContext, SyntaxCounter},
+    },
     stats::{CodeStats, Report},
-    utils::{ext::SliceExt, fs as fsutils},
+    utils::{
+        encoding,
+        ext::SliceExt,
+        fs as fsutils,
+        line_ending::{self, LineEndings},
+    },
 };
 
-use encoding_rs_io::DecodeReaderBytesBuilder;
-use grep_searcher::{LineIter, LineStep};
+use grep_searcher::LineStep;
 use rayon::prelude::*;
 
 use self::LanguageType::*;
 
+/// How many bytes [`LanguageType::parse_from_reader`] reads at a time.
+const READ_CHUNK_SIZE: usize = 1 << 20;
+
+/// How many bytes of lines a block of an embedded language can hold back
+/// while its end hasn't been read, before they're counted as the host's text.
+const MAX_LOOKAHEAD: usize = READ_CHUNK_SIZE;
+
 include!(concat!(env!("OUT_DIR"), "/language_type.rs"));
 
 impl LanguageType {
     /// Parses a given [`Path`] using the [`LanguageType`]. Returning [`Report`]
     /// on success and giving back ownership of [`PathBuf`] on error.
+    ///
+    /// The file is decoded with the encoding [`Config::encodings`] sets for
+    /// its path, or else the encoding detected from its contents, which the
+    /// report records.
     pub fn parse(self, path: PathBuf, config: &Config) -> Result<Report, (io::Error, PathBuf)> {
-        let text = {
-            let f = match File::open(&path) {
-                Ok(f) => f,
-                Err(e) => return Err((e, path)),
-            };
-            let mut s = Vec::new();
-            let mut reader = DecodeReaderBytesBuilder::new().build(f);
+        let mut reader = match encoding::open(&path, config) {
+            Ok(reader) => reader,
+            Err(e) => return Err((e, path)),
+        };
 
-            if let Err(e) = reader.read_to_end(&mut s) {
-                return Err((e, path));
+        let mut line_endings = LineEndings::default();
+
+        match self.parse_chunks(
+            &mut reader,
+            Some(&path),
+            config,
+            READ_CHUNK_SIZE,
+            &mut line_endings,
+        ) {
+            Ok(code_stats) => {
+                let mut stats = Report::new(path);
+                stats += code_stats;
+                stats.encoding = Some(reader.encoding().name().to_owned());
+                stats.lossy = reader.is_lossy();
+                stats.line_ending = line_endings.style();
+                Ok(stats)
             }
-            s
-        };
+            Err(e) => Err((e, path)),
+        }
+    }
 
-        let mut stats = Report::new(path);
+    /// Parses the bytes read from `reader` as the given [`LanguageType`].
+    /// The text is read and counted in chunks rather than all at once, so
+    /// memory use stays flat however large the input is. A block of an
+    /// embedded language is held in memory until it ends, for up to a MiB,
+    /// after which its lines are counted as the host's own text.
+    ///
+    /// Jupyter notebooks have to be read in full before they can be counted.
+    ///
+    /// ```
+    /// use tokei::{Config, LanguageType};
+    ///
+    /// let text = "fn main() {\n    // Hello\n}\n";
+    /// let stats = LanguageType::Rust
+    ///     .parse_from_reader(text.as_bytes(), &Config::default())
+    ///     .unwrap();
+    ///
+    /// assert_eq!(stats, LanguageType::Rust.parse_from_str(text, &Config::default()));
+    /// ```
+    pub fn parse_from_reader<R: Read>(self, reader: R, config: &Config) -> io::Result<CodeStats> {
+        self.parse_chunks(
+            reader,
+            None,
+            config,
+            READ_CHUNK_SIZE,
+            &mut LineEndings::default(),
+        )
+    }
+
+    /// Parses the bytes read from `reader` in chunks of `chunk_size`. Given
+    /// the `path` of the file they're read from, the embedding rules that
+    /// only apply to some paths are used too.
+    fn parse_chunks<R: Read>(
+        self,
+        reader: R,
+        path: Option<&Path>,
+        config: &Config,
+        chunk_size: usize,
+        line_endings: &mut LineEndings,
+    ) -> io::Result<CodeStats> {
+        self.read_chunks(
+            reader,
+            path,
+            config,
+            chunk_size,
+            MAX_LOOKAHEAD,
+            line_endings,
+        )
+        .map(|(stats, _)| stats)
+    }
+
+    /// Like [`LanguageType::parse_chunks`], holding back no more than
+    /// `lookahead` bytes for a block that hasn't ended, and also returning the
+    /// most bytes that were held in memory at once.
+    fn read_chunks<R: Read>(
+        self,
+        mut reader: R,
+        path: Option<&Path>,
+        config: &Config,
+        chunk_size: usize,
+        lookahead: usize,
+        line_endings: &mut LineEndings,
+    ) -> io::Result<(CodeStats, usize)> {
+        if self == LanguageType::Jupyter {
+            let mut text = Vec::new();
+            reader.read_to_end(&mut text)?;
+            let read = text.len();
+            return Ok((self.parse_from_slice(text, config), read));
+        }
+
+        let mut syntax = SyntaxCounter::new(self);
+        syntax.path = path.map(Arc::from);
+        let mut stats = CodeStats::new();
+        let mut buffer = Vec::with_capacity(chunk_size);
+        let mut normalised = 0;
+        let mut eof = false;
+        let mut held = 0;
+
+        while !eof {
+            let read = reader
+                .by_ref()
+                .take(chunk_size as u64)
+                .read_to_end(&mut buffer)?;
+            eof = read < chunk_size;
+            held = held.max(buffer.len());
+            normalised += line_endings.normalise(&mut buffer[normalised..], eof);
+
+            // Only complete lines can be counted until the end of the input.
+            let end = if eof {
+                buffer.len()
+            } else {
+                match buffer[..normalised].iter().rposition(|&c| c == b'\n') {
+                    Some(i) => i + 1,
+                    None => continue,
+                }
+            };
 
-        stats += self.parse_from_slice(&text, config);
+            let (chunk_stats, consumed) =
+                self.classify_chunk::<CodeStats>(config, &buffer[..end], &mut syntax, eof);
+            stats += chunk_stats;
+            buffer.drain(..consumed);
+            normalised -= consumed;
+
+            // A block that's held back more than `lookahead` is counted as
+            // the host's text, rather than read again with every chunk until
+            // it ends.
+            let pending = end - consumed;
+            if pending > lookahead {
+                syntax.ignore_embeddings = true;
+                let (chunk_stats, consumed) = self.classify_chunk::<CodeStats>(
+                    config,
+                    &buffer[..pending],
+                    &mut syntax,
+                    false,
+                );
+                syntax.ignore_embeddings = false;
+
+                stats += chunk_stats;
+                buffer.drain(..consumed);
+                normalised -= consumed;
+            }
+        }
 
-        Ok(stats)
+        Ok((stats, held))
     }
 
     /// Parses the text provided as the given [`LanguageType`].
@@ -57,80 +207,176 @@ impl LanguageType {
         let text = text.as_ref();
 
         if self == LanguageType::Jupyter {
-            return self
-                .parse_jupyter(text.as_ref(), config)
-                .unwrap_or_else(CodeStats::new);
+            return self.parse_jupyter(text, config).unwrap_or_default();
         }
 
-        let syntax = SyntaxCounter::new(self);
-
-        if let Some(end) = syntax
-            .shared
-            .important_syntax
-            .earliest_find(text)
-            .and_then(|m| {
-                // Get the position of the last line before the important
-                // syntax.
-                text[..=m.start()]
-                    .iter()
-                    .rev()
-                    .position(|&c| c == b'\n')
-                    .filter(|&p| p != 0)
-                    .map(|p| m.start() - p)
-            })
-        {
-            let (skippable_text, rest) = text.split_at(end + 1);
-            let is_fortran = syntax.shared.is_fortran;
-            let is_literate = syntax.shared.is_literate;
-            let comments = syntax.shared.line_comments;
+        self.classify(&line_ending::normalise(text), config)
+    }
+
+    /// Classifies each line of the bytes provided as the given
+    /// [`LanguageType`]. The lines are yielded in order, and lines belonging
+    /// to embedded languages record which languages they were found in. The
+    /// totals returned by [`LanguageType::parse_from_slice`] are built from
+    /// this same classification.
+    ///
+    /// Jupyter notebooks are counted from the contents of their cells rather
+    /// than the lines of the file, so classifying one only classifies the
+    /// notebook's raw JSON.
+    ///
+    /// ```
+    /// use tokei::{Config, LanguageType, LineKind};
+    ///
+    /// let text = "<p>Hi</p>\n<script>\n// Greet\nalert(1);\n</script>\n";
+    /// let lines: Vec<_> = LanguageType::Html
+    ///     .classify_lines(text.as_bytes(), &Config::default())
+    ///     .collect();
+    ///
+    /// assert_eq!(lines[2].kind, LineKind::Comment);
+    /// assert_eq!(lines[2].child_language(), Some(LanguageType::JavaScript));
+    /// assert_eq!(&text[lines[3].range.clone()], "alert(1);");
+    /// ```
+    pub fn classify_lines(
+        self,
+        text: &[u8],
+        config: &Config,
+    ) -> impl Iterator<Item = ClassifiedLine> {
+        self.classify::<Vec<ClassifiedLine>>(&line_ending::normalise(text), config)
+            .into_iter()
+    }
+
+    /// Classifies each line of the bytes read from the file at `path` as the
+    /// given [`LanguageType`], like [`LanguageType::classify_lines`]. Knowing
+    /// the path, the embedding rules that only apply to some files are used
+    /// as well, such as those for the shell steps of CI workflows.
+    ///
+    /// ```
+    /// use std::path::Path;
+    /// use tokei::{Config, LanguageType};
+    ///
+    /// let text = "script:\n  - cargo test\n";
+    /// let path = Path::new(".gitlab-ci.yml");
+    /// let lines: Vec<_> = LanguageType::Yaml
+    ///     .classify_file_lines(path, text.as_bytes(), &Config::default())
+    ///     .collect();
+    ///
+    /// assert_eq!(lines[1].child_language(), Some(LanguageType::Bash));
+    /// ```
+    pub fn classify_file_lines(
+        self,
+        path: &Path,
+        text: &[u8],
+        config: &Config,
+    ) -> impl Iterator<Item = ClassifiedLine> {
+        let mut syntax = SyntaxCounter::new(self);
+        syntax.path = Some(Arc::from(path));
+
+        self.classify_chunk::<Vec<ClassifiedLine>>(
+            config,
+            &line_ending::normalise(text),
+            &mut syntax,
+            true,
+        )
+        .0
+        .into_iter()
+    }
+
+    pub(crate) fn classify<S: Classification>(self, text: &[u8], config: &Config) -> S {
+        let mut syntax = SyntaxCounter::new(self);
+
+        self.classify_chunk(config, text, &mut syntax, true).0
+    }
+
+    /// Classifies `text` as the code of a language embedded in its host,
+    /// such as the PHP in a PHP file.
+    pub(crate) fn classify_as_guest<S: Classification>(self, text: &[u8], config: &Config) -> S {
+        let mut syntax = SyntaxCounter::guest(self);
+
+        self.classify_chunk(config, text, &mut syntax, true).0
+    }
+
+    /// Classifies `text` as documentation written in the language, such as
+    /// the XML of a C# doc comment, where every non-blank line is a comment.
+    pub(crate) fn classify_as_documentation<S: Classification>(
+        self,
+        text: &[u8],
+        config: &Config,
+    ) -> S {
+        let mut syntax = SyntaxCounter::documentation(self);
+
+        self.classify_chunk(config, text, &mut syntax, true).0
+    }
+
+    /// Classifies the lines of `text`, starting from the state in `syntax`.
+    /// Unless `eof` is set, `text` is followed by more input, and
+    /// classification stops before the first line that opens an embedded
+    /// language continuing past the end of `text`. Returns the classified
+    /// lines along with how many bytes of `text` they cover.
+    fn classify_chunk<S: Classification>(
+        self,
+        config: &Config,
+        text: &[u8],
+        syntax: &mut SyntaxCounter,
+        eof: bool,
+    ) -> (S, usize) {
+        let prefix_end = if syntax.is_plain_mode() {
+            syntax
+                .shared
+                .important_syntax
+                .earliest_find(text)
+                .and_then(|m| {
+                    // Get the position of the last line before the important
+                    // syntax.
+                    text[..=m.start()]
+                        .iter()
+                        .rev()
+                        .position(|&c| c == b'\n')
+                        .filter(|&p| p != 0)
+                        .map(|p| m.start() - p)
+                })
+        } else {
+            None
+        };
+
+        let (stats, consumed) = if let Some(end) = prefix_end {
+            let skippable_text = &text[..=end];
+            let shared = syntax.shared.clone();
             trace!(
                 "Using Simple Parse on {:?}",
                 String::from_utf8_lossy(skippable_text)
             );
-            let parse_lines = move || self.parse_lines(config, rest, CodeStats::new(), syntax);
-            let simple_parse = move || {
-                LineIter::new(b'\n', skippable_text)
-                    .par_bridge()
-                    .map(|line| {
-                        // FORTRAN has a rule where it only counts as a comment if it's the
-                        // first character in the column, so removing starting whitespace
-                        // could cause a miscount.
-                        let line = if is_fortran { line } else { line.trim() };
-                        if line.trim().is_empty() {
-                            (1, 0, 0)
-                        } else if is_literate
-                            || comments.iter().any(|c| line.starts_with(c.as_bytes()))
-                        {
-                            (0, 0, 1)
-                        } else {
-                            (0, 1, 0)
-                        }
-                    })
-                    .reduce(|| (0, 0, 0), |a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2))
-            };
+            let parse_lines = || self.parse_lines(config, text, end + 1, &mut *syntax, eof);
+            let simple_parse =
+                move || S::simple_lines(skippable_text, |line| shared.classify_plain_line(line));
 
-            let (mut stats, (blanks, code, comments)) = rayon::join(parse_lines, simple_parse);
+            let ((rest, consumed), mut stats) = rayon::join(parse_lines, simple_parse);
 
-            stats.blanks += blanks;
-            stats.code += code;
-            stats.comments += comments;
-            stats
+            stats.append(rest);
+            (stats, consumed)
         } else {
-            self.parse_lines(config, text, CodeStats::new(), syntax)
+            self.parse_lines(config, text, 0, syntax, eof)
+        };
+
+        if consumed != 0 {
+            syntax.at_start_of_file = false;
         }
+
+        (stats, consumed)
     }
 
     #[inline]
-    fn parse_lines(
+    fn parse_lines<S: Classification>(
         self,
         config: &Config,
         lines: &[u8],
-        mut stats: CodeStats,
-        mut syntax: SyntaxCounter,
-    ) -> CodeStats {
-        let mut stepper = LineStep::new(b'\n', 0, lines.len());
+        start: usize,
+        syntax: &mut SyntaxCounter,
+        eof: bool,
+    ) -> (S, usize) {
+        let mut stats = S::default();
+        let mut stepper = LineStep::new(b'\n', start, lines.len());
 
         while let Some((start, end)) = stepper.next(lines) {
+            let range = line_range(lines, start, end);
             let line = &lines[start..end];
             // FORTRAN has a rule where it only counts as a comment if it's the
             // first character in the column, so removing starting whitespace
@@ -142,66 +388,102 @@ impl LanguageType {
             };
             trace!("{}", String::from_utf8_lossy(line));
 
-            if syntax.try_perform_single_line_analysis(line, &mut stats) {
+            if let Some((kind, reason)) = syntax.try_perform_single_line_analysis(line) {
+                stats.push_line(range, kind, reason);
                 continue;
             }
 
+            // The line may have to be analysed again once more input has been
+            // read, so keep the state it started with.
+            let checkpoint = if eof { None } else { Some(syntax.clone()) };
+            let starting_depth = syntax.stack.len();
             let started_in_comments = !syntax.stack.is_empty()
                 || (config.treat_doc_strings_as_comments == Some(true)
                     && syntax.quote.is_some()
                     && syntax.quote_is_doc_quote);
             let ended_with_comments =
-                match syntax.perform_multi_line_analysis(lines, start, end, config) {
-                    crate::language::syntax::AnalysisReport::Normal(end) => end,
-                    crate::language::syntax::AnalysisReport::ChildLanguage(FileContext {
+                match syntax.perform_multi_line_analysis(lines, start, end, config, eof) {
+                    AnalysisReport::Normal(end) => end,
+                    AnalysisReport::ChildLanguage(FileContext {
                         language,
-                        end,
+                        opening_line,
+                        closing_line,
                         stats: blob,
+                        offset,
+                        end: resume,
                     }) => {
-                        match language {
-                            LanguageContext::Markdown { balanced, language } => {
-                                // Add the lines for the code fences.
-                                stats.comments += if balanced { 2 } else { 1 };
-                                // Add the code inside the fence to the stats.
-                                *stats.blobs.entry(language).or_default() += blob;
-                            }
-                            LanguageContext::Rust => {
-                                // Add all the markdown blobs.
-                                *stats.blobs.entry(LanguageType::Markdown).or_default() += blob;
-                            }
-                            LanguageContext::Html { language } => {
-                                stats.code += 1;
-                                // Add all the markdown blobs.
-                                *stats.blobs.entry(language).or_default() += blob;
+                        let reason = LineReason::EmbeddedLanguage(language);
+                        if let Some(kind) = opening_line {
+                            stats.push_line(range, kind, reason);
+
+                            // The child can be opened by more than one line.
+                            let mut opening = LineStep::new(b'\n', end, offset);
+                            while let Some((start, end)) = opening.next(lines) {
+                                stats.push_line(line_range(lines, start, end), kind, reason);
                             }
                         }
+                        stats.push_child(language, blob, offset);
+                        if let Some((start, kind)) = closing_line {
+                            stats.push_line(line_range(lines, start, resume), kind, reason);
+                        }
+
+                        // Advance to after the language code.
+                        stepper = LineStep::new(b'\n', resume, lines.len());
+                        continue;
+                    }
+                    AnalysisReport::PlainText(end) => {
+                        let mut text = LineStep::new(b'\n', start, end);
+                        while let Some((start, end)) = text.next(lines) {
+                            let (kind, reason) =
+                                syntax.shared.classify_plain_line(lines[start..end].trim());
+                            stats.push_line(line_range(lines, start, end), kind, reason);
+                        }
 
-                        // Advance to after the language code and the delimiter..
                         stepper = LineStep::new(b'\n', end, lines.len());
                         continue;
                     }
+                    AnalysisReport::Incomplete => {
+                        if let Some(checkpoint) = checkpoint {
+                            *syntax = checkpoint;
+                        }
+                        return (stats, start);
+                    }
                 };
             trace!("{}", String::from_utf8_lossy(line));
 
-            if syntax.shared.is_literate
-                || syntax.line_is_comment(line, config, ended_with_comments, started_in_comments)
-            {
-                stats.comments += 1;
-                trace!("Comment No.{}", stats.comments);
+            let comment = if syntax.shared.is_literate {
+                Some(LineReason::Literate)
+            } else {
+                syntax.line_is_comment(
+                    line,
+                    config,
+                    ended_with_comments,
+                    started_in_comments,
+                    starting_depth,
+                )
+            };
+
+            if let Some(reason) = comment {
+                stats.push_line(range, LineKind::Comment, reason);
+                trace!("Comment ({})", reason);
                 trace!("Was the Comment stack empty?: {}", !started_in_comments);
+            } else if syntax.quote.is_some() {
+                stats.push_line(range, LineKind::Code, LineReason::String);
+                trace!("Code (string)");
             } else {
-                stats.code += 1;
-                trace!("Code No.{}", stats.code);
+                stats.push_line(range, LineKind::Code, LineReason::Code);
+                trace!("Code");
             }
         }
 
-        stats
+        (stats, lines.len())
     }
 
     fn parse_jupyter(&self, json: &[u8], config: &Config) -> Option<CodeStats> {
         #[derive(Deserialize)]
         struct Jupyter {
-            cells: Vec<JupyterCell>,
+            cells: Vec<serde_json::Value>,
+            #[serde(default)]
             metadata: JupyterMetadata,
         }
 
@@ -210,17 +492,48 @@ impl LanguageType {
         enum CellType {
             Markdown,
             Code,
+            #[serde(other)]
+            Raw,
         }
 
         #[derive(Deserialize)]
         struct JupyterCell {
             cell_type: CellType,
-            source: Vec<String>,
+            #[serde(default)]
+            source: JupyterSource,
+            #[serde(default)]
+            metadata: serde_json::Value,
         }
 
+        /// The source of a cell, which the notebook format allows to be
+        /// either a list of lines or a single string.
         #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum JupyterSource {
+            Lines(Vec<String>),
+            Text(String),
+        }
+
+        impl Default for JupyterSource {
+            fn default() -> Self {
+                JupyterSource::Text(String::new())
+            }
+        }
+
+        impl JupyterSource {
+            fn into_text(self) -> String {
+                match self {
+                    JupyterSource::Lines(lines) => lines.concat(),
+                    JupyterSource::Text(text) => text,
+                }
+            }
+        }
+
+        #[derive(Default, Deserialize)]
         struct JupyterMetadata {
+            #[serde(default)]
             kernelspec: serde_json::Value,
+            #[serde(default)]
             language_info: serde_json::Value,
         }
 
@@ -240,22 +553,62 @@ impl LanguageType {
                     .language_info
                     .get("file_extension")
                     .and_then(serde_json::Value::as_str)
+                    .map(|extension| extension.trim_start_matches('.'))
                     .and_then(LanguageType::from_file_extension)
             })
             .unwrap_or(LanguageType::Python);
 
+        // Cells that don't follow the notebook format are skipped, rather
+        // than losing the whole notebook.
         let iter = jupyter
             .cells
-            .par_iter()
-            .map(|cell| match cell.cell_type {
-                CellType::Markdown => (
-                    LanguageType::Markdown,
-                    LanguageType::Markdown.parse_from_str(cell.source.join(""), config),
-                ),
-                CellType::Code => (
-                    language,
-                    language.parse_from_str(cell.source.join(""), config),
-                ),
+            .into_par_iter()
+            .filter_map(|cell| serde_json::from_value::<JupyterCell>(cell).ok())
+            .flat_map_iter(|cell| {
+                let JupyterCell {
+                    cell_type,
+                    source,
+                    metadata,
+                } = cell;
+                let source = source.into_text();
+
+                match cell_type {
+                    CellType::Markdown => vec![(
+                        LanguageType::Markdown,
+                        LanguageType::Markdown.parse_from_str(source, config),
+                    )],
+                    // Raw cells name the format they're written in, if any.
+                    CellType::Raw => {
+                        let format = metadata
+                            .get("format")
+                            .or_else(|| metadata.get("raw_mimetype"))
+                            .and_then(serde_json::Value::as_str)
+                            .and_then(LanguageType::from_mime)
+                            .unwrap_or(LanguageType::Text);
+
+                        vec![(format, format.parse_from_str(source, config))]
+                    }
+                    CellType::Code => {
+                        let (magic, body) =
+                            source.split_at(source.find('\n').map_or(source.len(), |i| i + 1));
+
+                        match cell_magic_language(magic) {
+                            // The magic line itself is code in the kernel's
+                            // language.
+                            Some(child) => vec![
+                                (
+                                    language,
+                                    CodeStats {
+                                        code: 1,
+                                        ..CodeStats::new()
+                                    },
+                                ),
+                                (child, child.parse_from_str(body, config)),
+                            ],
+                            None => vec![(language, language.parse_from_str(&source, config))],
+                        }
+                    }
+                }
             })
             .collect::<Vec<_>>();
 
@@ -268,10 +621,30 @@ impl LanguageType {
     }
 }
 
+/// The language of a notebook cell starting with the cell magic `line`, such
+/// as `%%bash` or `%%script ruby`, if it runs the cell as another language.
+fn cell_magic_language(line: &str) -> Option<LanguageType> {
+    let mut words = line.trim().strip_prefix("%%")?.split_whitespace();
+    let name = match words.next()? {
+        "script" => words.next()?,
+        "writefile" => {
+            return Path::new(words.last()?).extension().and_then(|extension| {
+                LanguageType::from_file_extension(&extension.to_string_lossy())
+            })
+        }
+        "latex" => return Some(LanguageType::Tex),
+        name => name,
+    };
+
+    embedding::child_language(Some(name.as_bytes()), Some(LanguageCapture::Name), None)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use crate::stats::LineEnding;
+
     use std::{fs, path::Path};
 
     #[test]
@@ -297,4 +670,286 @@ mod tests {
         assert_eq!(code, 528);
         assert_eq!(comments, 333);
     }
+
+    #[test]
+    fn jupyter_cells_are_counted_as_their_languages() {
+        let notebook = r#"{
+            "cells": [
+                {"cell_type": "code", "source": "%%bash\necho hi\n# done\n"},
+                {"cell_type": "code", "source": ["x = 1\n", "%%sql\n"]},
+                {"cell_type": "raw", "metadata": {"format": "text/latex"}, "source": "\\section{A}"},
+                {"cell_type": "raw", "source": ["Plain text\n"]},
+                {"cell_type": "heading", "source": "Unknown cell type"},
+                {"source": "No cell type"}
+            ]
+        }"#;
+
+        let stats = LanguageType::Jupyter
+            .parse_jupyter(notebook.as_bytes(), &Config::default())
+            .unwrap();
+        let blob = |language| &stats.blobs[&language];
+
+        assert_eq!(blob(LanguageType::Python).code, 3);
+        assert_eq!(blob(LanguageType::Bash).code, 1);
+        assert_eq!(blob(LanguageType::Bash).comments, 1);
+        assert_eq!(blob(LanguageType::Tex).code, 1);
+        assert_eq!(blob(LanguageType::Text).comments, 2);
+        assert_eq!(stats.lines(), 8);
+    }
+
+    #[test]
+    fn chunked_parse_matches_whole_file() {
+        let config = Config::default();
+
+        for entry in fs::read_dir(Path::new("tests").join("data")).unwrap() {
+            let path = entry.unwrap().path();
+            let language = match LanguageType::from_path(&path, &config) {
+                Some(language) => language,
+                None => continue,
+            };
+            let text = fs::read(&path).unwrap();
+            let expected = language.parse_from_slice(&text, &config);
+
+            for &chunk_size in &[1, 7, 64] {
+                let stats = language
+                    .parse_chunks(
+                        &text[..],
+                        None,
+                        &config,
+                        chunk_size,
+                        &mut LineEndings::default(),
+                    )
+                    .unwrap();
+
+                assert_eq!(
+                    stats,
+                    expected,
+                    "{} with {} byte chunks",
+                    path.display(),
+                    chunk_size
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn front_matter_only_opens_the_file() {
+        let config = Config::default();
+        let text = "+++\ntitle = \"tokei\"\n+++\n<p>\n+++\nnot = \"toml\"\n+++\n";
+
+        for &chunk_size in &[1, 64] {
+            let stats = LanguageType::Html
+                .parse_chunks(
+                    text.as_bytes(),
+                    None,
+                    &config,
+                    chunk_size,
+                    &mut LineEndings::default(),
+                )
+                .unwrap();
+
+            assert_eq!(stats.blobs.len(), 1);
+            assert_eq!(stats.blobs[&LanguageType::Toml].code, 1);
+            assert_eq!(stats.code, 5);
+        }
+    }
+
+    #[test]
+    fn unclosed_blocks_are_only_held_back_up_to_the_lookahead() {
+        let text = format!("```rust\n{}", "let x = 1;\n".repeat(100));
+        let read = |lookahead| {
+            LanguageType::Markdown
+                .read_chunks(
+                    text.as_bytes(),
+                    None,
+                    &Config::default(),
+                    64,
+                    lookahead,
+                    &mut LineEndings::default(),
+                )
+                .unwrap()
+        };
+
+        // Without a limit the block is held until the end of the file, which
+        // it runs to.
+        let (stats, held) = read(usize::MAX);
+        assert_eq!(stats.blobs[&LanguageType::Rust].code, 100);
+        assert_eq!(held, text.len());
+
+        let (stats, held) = read(64);
+        assert!(stats.blobs.is_empty());
+        assert_eq!(stats.lines(), 101);
+        assert!(held <= 3 * 64, "held {} bytes", held);
+    }
+
+    #[test]
+    fn custom_blocks_are_children_when_declared() {
+        let text = "<i18n>\n{\n  \"en\": {}\n}\n</i18n>\n<docs lang=\"md\">\n# Docs\n</docs>\n";
+
+        let stats = LanguageType::Vue.parse_from_str(text, &Config::default());
+        assert!(stats.blobs.is_empty());
+
+        let config = Config {
+            custom_blocks: Some(
+                vec![
+                    ("i18n".to_owned(), LanguageType::Json),
+                    ("docs".to_owned(), LanguageType::Text),
+                ]
+                .into_iter()
+                .collect(),
+            ),
+            ..Config::default()
+        };
+        for &chunk_size in &[1, 64] {
+            let stats = LanguageType::Vue
+                .parse_chunks(
+                    text.as_bytes(),
+                    None,
+                    &config,
+                    chunk_size,
+                    &mut LineEndings::default(),
+                )
+                .unwrap();
+
+            assert_eq!(stats.blobs[&LanguageType::Json].code, 3);
+            assert_eq!(stats.blobs[&LanguageType::Markdown].comments, 1);
+            assert_eq!(stats.code, 4);
+        }
+    }
+
+    #[test]
+    fn ci_scripts_are_shell_scripts_only_in_ci_files() {
+        let text =
+            "build:\n  script:\n    - cargo build\n  variables:\n    script:\n      ref: main\n";
+        let count = |path: &str| -> CodeStats {
+            LanguageType::Yaml
+                .classify_file_lines(Path::new(path), text.as_bytes(), &Config::default())
+                .collect()
+        };
+
+        let stats = count("ci/.gitlab-ci.yml");
+        assert_eq!(stats.blobs.len(), 1);
+        assert_eq!(stats.blobs[&LanguageType::Bash].code, 1);
+        assert_eq!(stats.code, 5);
+
+        let stats = count("config/.gitlab-ci.yml.example");
+        assert!(stats.blobs.is_empty());
+        assert_eq!(stats.code, 6);
+    }
+
+    #[test]
+    fn make_recipes_are_shell_scripts() {
+        let text =
+            "CC := gcc\nall: main.o\n\t$(CC) -o all main.o\n\t@echo done\n\nclean:\n\trm -f all";
+
+        for &chunk_size in &[1, 64] {
+            let stats = LanguageType::Makefile
+                .parse_chunks(
+                    text.as_bytes(),
+                    None,
+                    &Config::default(),
+                    chunk_size,
+                    &mut LineEndings::default(),
+                )
+                .unwrap();
+
+            assert_eq!(stats.blobs[&LanguageType::Sh].code, 3);
+            assert_eq!(stats.code, 3);
+            assert_eq!(stats.blanks, 1);
+        }
+    }
+
+    #[test]
+    fn doc_strings_are_children_when_treated_as_comments() {
+        let text = "\"\"\"\nDocs.\n\n    f()\n\"\"\"\nf() = 1\n";
+
+        let stats = LanguageType::Julia.parse_from_str(text, &Config::default());
+        assert!(stats.blobs.is_empty());
+        assert_eq!(stats.code, 6);
+
+        let config = Config {
+            treat_doc_strings_as_comments: Some(true),
+            ..Config::default()
+        };
+        let stats = LanguageType::Julia.parse_from_str(text, &config);
+        let docs = &stats.blobs[&LanguageType::Markdown];
+
+        assert_eq!((stats.code, stats.comments), (1, 2));
+        assert_eq!((docs.comments, docs.blanks), (2, 1));
+    }
+
+    #[test]
+    fn python_doc_strings_are_restructuredtext_unless_treated_as_comments() {
+        let text = "def f():\n    \"\"\"\n    Docs.\n\n    >>> f()\n    \"\"\"\n    pass\n";
+
+        let stats = LanguageType::Python.parse_from_str(text, &Config::default());
+        let docs = &stats.blobs[&LanguageType::ReStructuredText];
+
+        assert_eq!((stats.code, stats.comments), (4, 0));
+        assert_eq!((docs.code, docs.blanks), (1, 1));
+        assert_eq!(docs.blobs[&LanguageType::Python].code, 1);
+
+        let config = Config {
+            treat_doc_strings_as_comments: Some(true),
+            ..Config::default()
+        };
+        let stats = LanguageType::Python.parse_from_str(text, &config);
+
+        assert!(stats.blobs.is_empty());
+        assert_eq!((stats.code, stats.comments, stats.blanks), (2, 5, 0));
+    }
+
+    #[test]
+    fn csharp_doc_comments_are_xml_comments() {
+        let text = "/// <summary>\n/// Docs.\n/// </summary>\nclass C {}\n";
+
+        let stats = LanguageType::CSharp.parse_from_str(text, &Config::default());
+        let docs = &stats.blobs[&LanguageType::Xml];
+
+        assert_eq!((stats.code, stats.comments), (1, 0));
+        assert_eq!((docs.code, docs.comments), (0, 3));
+    }
+
+    #[test]
+    fn carriage_returns_count_as_line_endings() {
+        let config = Config::default();
+
+        for entry in fs::read_dir(Path::new("tests").join("data")).unwrap() {
+            let path = entry.unwrap().path();
+            let language = match LanguageType::from_path(&path, &config) {
+                Some(LanguageType::Jupyter) | None => continue,
+                Some(language) => language,
+            };
+            let text = fs::read(&path).unwrap();
+            if text.contains(&b'\r') {
+                continue;
+            }
+            let expected = language.parse_from_slice(&text, &config);
+
+            let cr: Vec<u8> = text
+                .iter()
+                .map(|&c| if c == b'\n' { b'\r' } else { c })
+                .collect();
+            let mut line_endings = LineEndings::default();
+            let stats = language
+                .parse_chunks(&cr[..], None, &config, 7, &mut line_endings)
+                .unwrap();
+
+            assert_eq!(stats, expected, "{}", path.display());
+            assert_eq!(language.parse_from_slice(&cr, &config), expected);
+            assert_eq!(line_endings.style(), Some(LineEnding::Cr));
+        }
+    }
+
+    #[test]
+    fn mixed_line_endings() {
+        let text = b"fn main() {\r\n    // a\r\r}\n";
+        let mut line_endings = LineEndings::default();
+        let stats = LanguageType::Rust
+            .parse_chunks(&text[..], None, &Config::default(), 1, &mut line_endings)
+            .unwrap();
+
+        assert_eq!((stats.code, stats.comments, stats.blanks), (2, 1, 1));
+        assert_eq!(line_endings.style(), Some(LineEnding::Mixed));
+    }
 }
@@ -4,7 +4,7 @@ use arbitrary::Arbitrary;
 /// information about the language, such as multi line comments, single line
 /// comments, string literal syntax, whether a given language allows nesting
 /// comments.
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, schemars::JsonSchema)]
 #[derive(Arbitrary, Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 #[non_exhaustive]
 #[allow(clippy::upper_case_acronyms)]
@@ -254,16 +254,179 @@ impl LanguageType {
                                    concat(with=important_syntax) -%}
                         "{{item}}",
                     {%- endfor -%}
-                    {%- for context in value.contexts | default(value=[]) -%}
-                        {% if value.kind == "html" %}
-                            "<{{context.tag}}",
-                        {% endif %}
+                    {%- for rule in value.embedding | default(value=[]) -%}
+                        {%- if rule.prefixes -%}
+                            {%- for item in rule.prefixes -%}
+                                "{{item}}",
+                            {%- endfor -%}
+                        {%- elif rule.fences -%}
+                            {%- for item in rule.fences -%}
+                                "{{item}}",
+                            {%- endfor -%}
+                        {%- elif rule.element -%}
+                            "<{{rule.element}}",
+                        {%- elif rule.custom_blocks -%}
+                            "<",
+                        {%- elif rule.tags -%}
+                            "`",
+                        {%- else -%}
+                            "{{rule.trigger}}",
+                        {%- endif -%}
                     {%- endfor -%}
                 ],
             {% endfor %}
         }
     }
 
+    /// Returns the language that files of this language are counted as, when
+    /// they're mostly made up of that language. PHP files, for example, are
+    /// counted as HTML, with the PHP code in them counted as a child language.
+    pub fn host(self) -> Option<Self> {
+        match self {
+            {% for key, value in languages -%}
+                {%- if value.host -%}
+                    {{key}} => Some({{value.host}}),
+                {% endif -%}
+            {%- endfor %}
+            _ => None,
+        }
+    }
+
+    /// Returns the rules for finding other languages embedded in this one.
+    pub(crate) fn embedding(self) -> &'static [EmbeddingRule] {
+        match self {
+            {% for key, value in languages -%}
+                {%- if value.embedding -%}
+                    {{key}} => &[
+                        {%- for rule in value.embedding -%}
+                            {%- if rule.prefixes -%}
+                                EmbeddingRule::Prefix {
+                                    prefixes: &[
+                                        {%- for item in rule.prefixes -%}
+                                            "{{item}}",
+                                        {%- endfor -%}
+                                    ],
+                                    language: {{rule.default}},
+                                    is_documentation: {{ rule.documentation | default(value=false) }},
+                                },
+                            {%- elif rule.fences -%}
+                                EmbeddingRule::Fence {
+                                    fences: &[
+                                        {%- for item in rule.fences -%}
+                                            "{{item}}",
+                                        {%- endfor -%}
+                                    ],
+                                    delimiters: {% if rule.delimiters | default(value="code") == "comment" -%}
+                                        LineKind::Comment
+                                    {%- else -%}
+                                        LineKind::Code
+                                    {%- endif %},
+                                },
+                            {%- elif rule.tags -%}
+                                EmbeddingRule::TaggedTemplate {
+                                    tags: &[
+                                        {%- for tag, language in rule.tags -%}
+                                            ("{{tag}}", {{language}}),
+                                        {%- endfor -%}
+                                    ],
+                                    delimiters: {% if rule.delimiters | default(value="code") == "comment" -%}
+                                        LineKind::Comment
+                                    {%- else -%}
+                                        LineKind::Code
+                                    {%- endif %},
+                                },
+                            {%- elif rule.element or rule.custom_blocks -%}
+                                EmbeddingRule::Element {
+                                    tag: {% if rule.element -%}
+                                        Some("{{rule.element}}")
+                                    {%- else -%}
+                                        None
+                                    {%- endif %},
+                                    triggers: &[{% if rule.element -%}
+                                        "<{{rule.element}}"
+                                    {%- else -%}
+                                        "<"
+                                    {%- endif %}],
+                                    default: {% if rule.default -%}
+                                        Some({{rule.default}})
+                                    {%- else -%}
+                                        None
+                                    {%- endif %},
+                                    delimiters: {% if rule.delimiters | default(value="code") == "comment" -%}
+                                        LineKind::Comment
+                                    {%- else -%}
+                                        LineKind::Code
+                                    {%- endif %},
+                                },
+                            {%- else -%}
+                                EmbeddingRule::Block {
+                                    triggers: &["{{rule.trigger}}"],
+                                    start: r##"{{rule.start}}"##,
+                                    start_lines: {{ rule.start_lines | default(value=1) }},
+                                    end: {% if rule.end -%}
+                                        Some(r##"{{rule.end}}"##)
+                                    {%- else -%}
+                                        None
+                                    {%- endif %},
+                                    {%- set capture = rule.capture | default(value="") %}
+                                    capture: {% if capture == "mime" -%}
+                                        Some(LanguageCapture::Mime)
+                                    {%- elif capture == "name" -%}
+                                        Some(LanguageCapture::Name)
+                                    {%- else -%}
+                                        None
+                                    {%- endif %},
+                                    default: {% if rule.default -%}
+                                        Some({{rule.default}})
+                                    {%- else -%}
+                                        None
+                                    {%- endif %},
+                                    delimiters: {% if rule.delimiters | default(value="code") == "comment" -%}
+                                        LineKind::Comment
+                                    {%- else -%}
+                                        LineKind::Code
+                                    {%- endif %},
+                                    runs_to_end_of_file: {{ rule.unclosed | default(value="ignore") == "end_of_file" }},
+                                    only_at_start_of_file: {{ rule.at | default(value="any_line") == "start_of_file" }},
+                                    opening_line_is_child: {{ rule.opening | default(value="delimiters") == "child" }},
+                                    {%- set closing = rule.closing | default(value="host") %}
+                                    closing_line: {% if closing == "delimiters" -%}
+                                        ClosingLine::Delimiters
+                                    {%- elif closing == "child" -%}
+                                        ClosingLine::Child
+                                    {%- else -%}
+                                        ClosingLine::Host
+                                    {%- endif %},
+                                    line_prefix: {% if rule.line_prefix -%}
+                                        Some("{{rule.line_prefix}}")
+                                    {%- else -%}
+                                        None
+                                    {%- endif %},
+                                    doc_strings_as_comments: {% if rule.doc_strings -%}
+                                        Some({{ rule.doc_strings == "as_comments" }})
+                                    {%- else -%}
+                                        None
+                                    {%- endif %},
+                                    paths: &[
+                                        {%- for item in rule.paths | default(value=[]) -%}
+                                            "{{item}}",
+                                        {%- endfor -%}
+                                    ],
+                                    first_line: {% if rule.first_line -%}
+                                        Some(r##"{{rule.first_line}}"##)
+                                    {%- else -%}
+                                        None
+                                    {%- endif %},
+                                },
+                            {%- endif -%}
+                        {%- endfor -%}
+                    ],
+                {% endif -%}
+            {%- endfor %}
+            _ => &[],
+        }
+    }
+
     /// Get language from a file path. May open and read the file.
     ///
     /// ```no_run
@@ -294,7 +457,7 @@ impl LanguageType {
 
         match fsutils::get_extension(entry) {
             Some(extension) => LanguageType::from_file_extension(extension.as_str()),
-            None => LanguageType::from_shebang(&entry),
+            None => LanguageType::from_shebang(entry),
         }
     }
 
@@ -1,16 +1,20 @@
 use std::{
+    cmp::Reverse,
     collections::{btree_map, BTreeMap},
     iter::IntoIterator,
     ops::{AddAssign, Deref, DerefMut},
-    path::Path,
+    path::{Component, Path, PathBuf},
 };
 
 use rayon::prelude::*;
 
 use crate::{
     config::Config,
-    language::{Language, LanguageType},
-    utils,
+    groups::Groups,
+    language::{file_lines, Language, LanguageType},
+    owners::CodeOwners,
+    stats::{Distribution, Report, Summary},
+    utils::{self, path::normalise},
 };
 
 /// A newtype representing a list of languages counted in the provided
@@ -42,22 +46,51 @@ impl<'de> serde::Deserialize<'de> for Languages {
     }
 }
 
+impl schemars::JsonSchema for Languages {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "Languages".into()
+    }
+
+    fn json_schema(generator: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        BTreeMap::<LanguageType, Language>::json_schema(generator)
+    }
+}
+
 impl Languages {
     fn from_previous(map: BTreeMap<LanguageType, Language>) -> Self {
-        use std::collections::btree_map::Entry;
         let mut me = Self::new();
+        me.merge(map);
+        me
+    }
 
-        for (name, input_language) in map {
-            match me.entry(name) {
-                Entry::Occupied(mut entry) => {
-                    *entry.get_mut() += input_language;
-                }
-                Entry::Vacant(entry) => {
-                    entry.insert(input_language);
+    /// Merges the results of another run into these, such as the results of
+    /// another shard of a repository. Languages missing from `self` are
+    /// added, and the others are merged with [`Language::merge`], so a file
+    /// counted in both runs is only counted once.
+    ///
+    /// ```no_run
+    /// use tokei::{Config, Languages};
+    ///
+    /// let mut languages = Languages::new();
+    /// languages.get_statistics(&["src"], &[], &Config::default());
+    ///
+    /// let mut tests = Languages::new();
+    /// tests.get_statistics(&["tests"], &[], &Config::default());
+    ///
+    /// languages.merge(tests);
+    /// ```
+    pub fn merge<I>(&mut self, other: I)
+    where
+        I: IntoIterator<Item = (LanguageType, Language)>,
+    {
+        for (ty, language) in other {
+            match self.inner.entry(ty) {
+                btree_map::Entry::Occupied(mut entry) => entry.get_mut().merge(language),
+                btree_map::Entry::Vacant(entry) => {
+                    entry.insert(language);
                 }
             }
         }
-        me
     }
 
     /// Populates the `Languages` struct with statistics about languages
@@ -109,9 +142,189 @@ impl Languages {
             total.code += language.code;
             total.inaccurate |= language.inaccurate;
             total.children.insert(*ty, language.reports.clone());
+            if let Some(summary) = language.summary {
+                *total.summary.get_or_insert_with(Summary::default) += summary;
+            }
         }
         total
     }
+
+    /// How the lines of every file are distributed, counting the lines of
+    /// the languages embedded in them. Returns `None` if there aren't any
+    /// reports. See [`Language::distribution`] for each language.
+    #[must_use]
+    pub fn distribution(&self) -> Option<Distribution> {
+        Distribution::new(self.values().flat_map(|l| &l.reports).map(file_lines))
+    }
+
+    /// The `n` largest files of every language by their lines, counting the
+    /// lines of the languages embedded in them, largest first. See
+    /// [`Language::largest`] for each language.
+    ///
+    /// ```no_run
+    /// use tokei::{Config, Languages};
+    ///
+    /// let mut languages = Languages::new();
+    /// languages.get_statistics(&["."], &[".git", "target"], &Config::default());
+    ///
+    /// for (language, report) in languages.largest(10) {
+    ///     println!("{} {}: {}", language, report.path().display(), report.stats.lines());
+    /// }
+    /// ```
+    #[must_use]
+    pub fn largest(&self, n: usize) -> Vec<(LanguageType, &Report)> {
+        let mut largest: Vec<_> = self
+            .iter()
+            .flat_map(|(ty, language)| language.largest(n).into_iter().map(move |r| (*ty, r)))
+            .collect();
+        largest.sort_by_cached_key(|(_, report)| (Reverse(file_lines(report)), report.path()));
+        largest.truncate(n);
+        largest
+    }
+
+    /// Rolls the reports up into the directories that contain them, cut off
+    /// at `depth` directories below the counted paths. Files in shallower
+    /// directories are counted in the directory they're in, and files at the
+    /// top of the counted paths are counted under `.`.
+    ///
+    /// ```no_run
+    /// use tokei::{Config, Languages};
+    ///
+    /// let mut languages = Languages::new();
+    /// languages.get_statistics(&["."], &[".git", "target"], &Config::default());
+    ///
+    /// for (directory, languages) in languages.by_directory(1) {
+    ///     println!("{}: {}", directory.display(), languages.total().code);
+    /// }
+    /// ```
+    #[must_use]
+    pub fn by_directory(&self, depth: usize) -> BTreeMap<PathBuf, Languages> {
+        self.group_by(|report| Some(directory_at_depth(&report.path(), depth)))
+    }
+
+    /// Rolls the reports up into the owners of their files in `owners`. A
+    /// file with more than one owner is counted for each of them, and files
+    /// without owners are counted under [`CodeOwners::UNOWNED`]. Relative
+    /// paths are resolved against the current directory, and files are
+    /// looked up where they were counted, even if
+    /// [`Config::path_prefixes`] renamed their reports.
+    ///
+    /// ```no_run
+    /// use tokei::{CodeOwners, Config, Languages};
+    ///
+    /// let mut languages = Languages::new();
+    /// languages.get_statistics(&["."], &[".git", "target"], &Config::default());
+    ///
+    /// let owners = CodeOwners::find(".").unwrap().expect("no CODEOWNERS file");
+    /// for (owner, languages) in languages.by_owner(&owners) {
+    ///     println!("{}: {}", owner, languages.total().code);
+    /// }
+    /// ```
+    #[must_use]
+    pub fn by_owner(&self, owners: &CodeOwners) -> BTreeMap<String, Languages> {
+        let current_dir = std::env::current_dir()
+            .and_then(std::fs::canonicalize)
+            .unwrap_or_default();
+
+        self.group_by(|report| {
+            let owners = owners.owners_of(&current_dir.join(report.counted_path()));
+
+            if owners.is_empty() {
+                vec![String::from(CodeOwners::UNOWNED)]
+            } else {
+                owners.to_vec()
+            }
+        })
+    }
+
+    /// Rolls the reports up into the `groups` their files are in. A file in
+    /// more than one group is counted for each of them, and files that aren't
+    /// in any group are counted under [`Groups::UNGROUPED`]. Paths are matched
+    /// relative to the current directory, using the paths files were counted
+    /// from rather than any [`Config::path_prefixes`] rewrites.
+    ///
+    /// ```no_run
+    /// use tokei::{Config, Groups, Languages};
+    ///
+    /// let mut languages = Languages::new();
+    /// languages.get_statistics(&["."], &[".git", "target"], &Config::default());
+    ///
+    /// let groups = Groups::new(vec![("frontend", vec!["web/**", "ui/**"])]).unwrap();
+    /// for (group, languages) in languages.by_group(&groups) {
+    ///     println!("{}: {}", group, languages.total().code);
+    /// }
+    /// ```
+    #[must_use]
+    pub fn by_group(&self, groups: &Groups) -> BTreeMap<String, Languages> {
+        let current_dir = std::env::current_dir()
+            .and_then(std::fs::canonicalize)
+            .unwrap_or_default();
+
+        self.group_by(|report| {
+            let path = normalise(&current_dir.join(report.counted_path()));
+            let names = groups.groups_of(path.strip_prefix(&current_dir).unwrap_or(&path));
+
+            if names.is_empty() {
+                vec![String::from(Groups::UNGROUPED)]
+            } else {
+                names.into_iter().map(String::from).collect()
+            }
+        })
+    }
+
+    /// Copies each report into the groups `groups` puts its file in, and
+    /// totals each group.
+    fn group_by<K, I, F>(&self, mut groups: F) -> BTreeMap<K, Languages>
+    where
+        K: Ord,
+        I: IntoIterator<Item = K>,
+        F: FnMut(&Report) -> I,
+    {
+        let mut grouped = BTreeMap::<K, Languages>::new();
+
+        for (ty, language) in self {
+            for report in &language.reports {
+                for group in groups(report) {
+                    let entry = grouped.entry(group).or_default().entry(*ty).or_default();
+
+                    entry.add_report(report.clone());
+                    entry.inaccurate |= language.inaccurate;
+                }
+            }
+        }
+
+        for languages in grouped.values_mut() {
+            languages.inner.values_mut().for_each(Language::total);
+        }
+
+        grouped
+    }
+}
+
+/// The directory containing `path`, keeping at most `depth` named
+/// directories. `.` components are skipped so `./src` and `src` are the same
+/// directory.
+fn directory_at_depth(path: &Path, depth: usize) -> PathBuf {
+    let mut directory = PathBuf::new();
+    let mut named = 0;
+
+    for component in path.parent().into_iter().flat_map(Path::components) {
+        match component {
+            Component::CurDir => {}
+            Component::Normal(_) if named == depth => break,
+            Component::Normal(_) => {
+                named += 1;
+                directory.push(component);
+            }
+            _ => directory.push(component),
+        }
+    }
+
+    if directory.as_os_str().is_empty() {
+        directory.push(".");
+    }
+
+    directory
 }
 
 impl IntoIterator for Languages {
@@ -141,13 +354,10 @@ impl<'a> IntoIterator for &'a mut Languages {
     }
 }
 
+/// Merges the results of another run, see [`Languages::merge`].
 impl AddAssign<BTreeMap<LanguageType, Language>> for Languages {
     fn add_assign(&mut self, rhs: BTreeMap<LanguageType, Language>) {
-        for (name, language) in rhs {
-            if let Some(result) = self.inner.get_mut(&name) {
-                *result += language;
-            }
-        }
+        self.merge(rhs);
     }
 }
 
@@ -164,3 +374,260 @@ impl DerefMut for Languages {
         &mut self.inner
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CodeStats, Report};
+
+    fn report(name: &str, code: usize) -> Report {
+        let mut report = Report::new(name.into());
+        report.stats.code = code;
+        report
+    }
+
+    #[test]
+    fn reports_roll_up_into_directories() {
+        let mut languages = Languages::new();
+        let rust = languages.entry(LanguageType::Rust).or_default();
+        rust.add_report(report("./build.rs", 1));
+        rust.add_report(report("./services/api/src/main.rs", 2));
+        rust.add_report(report("./services/api/src/lib.rs", 3));
+        rust.add_report(report("./services/web/main.rs", 4));
+        languages
+            .entry(LanguageType::Python)
+            .or_default()
+            .add_report(report("services/api/setup.py", 5));
+
+        let code = |directories: &BTreeMap<PathBuf, Languages>| {
+            directories
+                .iter()
+                .map(|(path, languages)| (path.clone(), languages.total().code))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(code(&languages.by_directory(0)), [(PathBuf::from("."), 15)]);
+        assert_eq!(
+            code(&languages.by_directory(2)),
+            [
+                (PathBuf::from("."), 1),
+                (PathBuf::from("services/api"), 10),
+                (PathBuf::from("services/web"), 4),
+            ]
+        );
+
+        let api = &languages.by_directory(2)[Path::new("services/api")];
+        assert_eq!(api[&LanguageType::Rust].code, 5);
+        assert_eq!(api[&LanguageType::Rust].reports.len(), 2);
+        assert_eq!(api[&LanguageType::Python].code, 5);
+    }
+
+    #[test]
+    fn merging_keeps_new_languages_and_replaces_reports() {
+        let mut blob = report("index.html", 3);
+        blob.stats.blobs.insert(
+            LanguageType::JavaScript,
+            CodeStats {
+                code: 2,
+                ..CodeStats::new()
+            },
+        );
+
+        let mut languages = Languages::new();
+        let html = languages.entry(LanguageType::Html).or_default();
+        html.add_report(blob.clone());
+        html.add_report(report("old.html", 1));
+        html.total();
+
+        let mut shard = BTreeMap::new();
+        let mut html = Language::new();
+        html.add_report(blob);
+        html.add_report(report("new.html", 4));
+        html.total();
+        shard.insert(LanguageType::Html, html);
+        let mut rust = Language::new();
+        rust.add_report(report("main.rs", 5));
+        rust.total();
+        shard.insert(LanguageType::Rust, rust);
+
+        languages += shard;
+
+        let html = &languages[&LanguageType::Html];
+        assert_eq!(html.code, 8);
+        assert_eq!(html.reports.len(), 3);
+        assert_eq!(html.children[&LanguageType::JavaScript].len(), 1);
+        assert_eq!(languages[&LanguageType::Rust].code, 5);
+        assert_eq!(languages.total().code, 15);
+    }
+
+    #[test]
+    fn merging_replaces_the_children_of_replaced_reports() {
+        let mut page = report("index.html", 3);
+        page.stats.blobs.insert(
+            LanguageType::JavaScript,
+            CodeStats {
+                code: 2,
+                ..CodeStats::new()
+            },
+        );
+
+        // The totals haven't been worked out, so replacing the report can't
+        // subtract its counts from them.
+        let mut html = Language::new();
+        html.add_report(page);
+
+        let mut rewritten = Language::new();
+        rewritten.add_report(report("./index.html", 1));
+        rewritten.total();
+
+        html.merge(rewritten);
+        assert_eq!(html.code, 1);
+        assert_eq!(html.reports.len(), 1);
+        assert!(html.children.is_empty());
+    }
+
+    #[test]
+    fn reports_roll_up_into_owners() {
+        let mut languages = Languages::new();
+        let rust = languages.entry(LanguageType::Rust).or_default();
+        rust.add_report(report("/repo/build.rs", 1));
+        rust.add_report(report("/repo/api/main.rs", 2));
+        rust.add_report(report("/repo/shared/lib.rs", 3));
+
+        let owners = CodeOwners::new("/repo", "/api/ @api\n/shared/ @api @web\n");
+        let code = languages
+            .by_owner(&owners)
+            .iter()
+            .map(|(owner, languages)| (owner.clone(), languages.total().code))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            code,
+            [
+                (String::from("@api"), 5),
+                (String::from("@web"), 3),
+                (String::from(CodeOwners::UNOWNED), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_roll_up_into_groups() {
+        let mut languages = Languages::new();
+        let javascript = languages.entry(LanguageType::JavaScript).or_default();
+        javascript.add_report(report("./web/index.js", 1));
+        javascript.add_report(report("ui/button.js", 2));
+        javascript.add_report(report("./deploy/web.js", 3));
+        languages
+            .entry(LanguageType::Hcl)
+            .or_default()
+            .add_report(report("./modules/vpc/main.tf", 4));
+        languages
+            .entry(LanguageType::Rust)
+            .or_default()
+            .add_report(report("./src/main.rs", 5));
+
+        let groups = Groups::new(vec![
+            ("frontend", vec!["web/**", "ui/**", "*.js"]),
+            ("infra", vec!["deploy/**", "*.tf"]),
+        ])
+        .unwrap();
+        let grouped = languages.by_group(&groups);
+        let code = grouped
+            .iter()
+            .map(|(group, languages)| (group.clone(), languages.total().code))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            code,
+            [
+                (String::from("frontend"), 6),
+                (String::from("infra"), 7),
+                (String::from(Groups::UNGROUPED), 5),
+            ]
+        );
+        assert_eq!(grouped["infra"][&LanguageType::Hcl].code, 4);
+        assert_eq!(grouped["infra"][&LanguageType::JavaScript].code, 3);
+    }
+
+    #[test]
+    fn summaries_total_the_same_as_reports() {
+        let paths = [Path::new("tests").join("data")];
+        let mut reported = Languages::new();
+        reported.get_statistics(&paths, &[], &Config::default());
+
+        let mut summarised = Languages::new();
+        let config = Config {
+            summary_only: Some(true),
+            ..Config::default()
+        };
+        summarised.get_statistics(&paths, &[], &config);
+
+        assert_eq!(
+            reported.keys().collect::<Vec<_>>(),
+            summarised.keys().collect::<Vec<_>>()
+        );
+        for (language, summarised) in &summarised {
+            let reported = &reported[language];
+
+            assert!(summarised.reports.is_empty());
+            assert_eq!(summarised.files(), reported.files());
+            assert_eq!(summarised.lines(), reported.lines());
+            assert_eq!(summarised.summarise().code, reported.summarise().code);
+        }
+
+        let (reported, summarised) = (reported.total(), summarised.total());
+        assert_eq!(
+            summarised.summary.as_ref().unwrap().files,
+            reported.children.values().map(Vec::len).sum::<usize>()
+        );
+        assert_eq!(summarised.lines(), reported.lines());
+    }
+
+    #[test]
+    fn largest_files_and_distribution_span_languages() {
+        let mut languages = Languages::new();
+        let rust = languages.entry(LanguageType::Rust).or_default();
+        rust.add_report(report("small.rs", 1));
+        rust.add_report(report("large.rs", 50));
+        let mut embedding = report("embedding.rs", 10);
+        embedding.stats.blobs.insert(
+            LanguageType::Markdown,
+            CodeStats {
+                code: 30,
+                ..CodeStats::new()
+            },
+        );
+        rust.add_report(embedding);
+        let c = languages.entry(LanguageType::C).or_default();
+        c.add_report(report("main.c", 20));
+
+        let largest: Vec<_> = languages
+            .largest(3)
+            .into_iter()
+            .map(|(ty, report)| (ty, report.name.to_str().unwrap()))
+            .collect();
+        assert_eq!(
+            largest,
+            [
+                (LanguageType::Rust, "large.rs"),
+                (LanguageType::Rust, "embedding.rs"),
+                (LanguageType::C, "main.c")
+            ]
+        );
+
+        let distribution = languages.distribution().unwrap();
+        assert_eq!(distribution.mean, 27.75);
+        assert_eq!(distribution.median, 20);
+        assert_eq!(distribution.p90, 50);
+        assert_eq!(distribution.max, 50);
+        assert_eq!(
+            languages[&LanguageType::Rust]
+                .distribution()
+                .unwrap()
+                .median,
+            40
+        );
+        assert!(Language::new().distribution().is_none());
+    }
+}
@@ -1,16 +1,31 @@
+mod classify;
 mod embedding;
 pub mod language_type;
 pub mod languages;
 mod syntax;
 
-use std::{collections::BTreeMap, mem, ops::AddAssign};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet},
+    mem,
+    ops::AddAssign,
+};
 
-pub use self::{language_type::*, languages::Languages};
+pub use self::{
+    classify::{ClassifiedLine, LineKind, LineReason},
+    language_type::*,
+    languages::Languages,
+};
 
-use crate::{sort::Sort, stats::Report};
+use crate::{
+    sort::Sort,
+    stats::{CodeStats, Distribution, Report, Summary},
+    utils::path::normalise,
+};
 
 /// A struct representing statistics about a single Language.
-#[derive(Clone, Debug, Deserialize, Default, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, Default, PartialEq, Serialize, schemars::JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Language {
     /// The total number of blank lines.
     pub blanks: usize,
@@ -24,6 +39,9 @@ pub struct Language {
     pub children: BTreeMap<LanguageType, Vec<Report>>,
     /// Whether this language had problems with file parsing
     pub inaccurate: bool,
+    /// The statistics of the files counted without keeping their reports.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<Summary>,
 }
 
 impl Language {
@@ -45,11 +63,18 @@ impl Language {
         self.blanks + self.code + self.comments
     }
 
+    /// Returns the total number of files, including the ones in the summary.
+    #[must_use]
+    pub fn files(&self) -> usize {
+        self.reports.len() + self.summary.as_ref().map_or(0, |summary| summary.files)
+    }
+
     /// Add a `Report` to the Language. This will not update the totals in the
     /// Language struct.
     pub fn add_report(&mut self, report: Report) {
         for (lang, stats) in &report.stats.blobs {
             let mut new_report = Report::new(report.name.clone());
+            new_report.root = report.root.clone();
             new_report.stats = stats.clone();
 
             self.children.entry(*lang).or_default().push(new_report);
@@ -58,6 +83,79 @@ impl Language {
         self.reports.push(report);
     }
 
+    /// Adds the statistics of a `Report` to the language's summary without
+    /// keeping the report. This will not update the totals in the Language
+    /// struct.
+    ///
+    /// ```
+    /// # use tokei::*;
+    /// let mut report = Report::new("src/main.rs".into());
+    /// report.stats.code = 10;
+    ///
+    /// let mut rust = Language::new();
+    /// rust.add_to_summary(&report);
+    /// rust.total();
+    ///
+    /// assert!(rust.reports.is_empty());
+    /// assert_eq!(rust.files(), 1);
+    /// assert_eq!(rust.code, 10);
+    /// ```
+    pub fn add_to_summary(&mut self, report: &Report) {
+        self.summary
+            .get_or_insert_with(Summary::default)
+            .add_report(report);
+    }
+
+    /// Merges the statistics of `other` into the language, along with its
+    /// reports and children. A report for a file that already has a report
+    /// replaces it along with its children, so merging the same results twice
+    /// doesn't count them twice. Files in summaries can't be told apart, so
+    /// summaries are added together. The totals are worked out afresh.
+    ///
+    /// ```
+    /// # use tokei::*;
+    /// let mut report = Report::new("src/main.rs".into());
+    /// report.stats.code = 10;
+    ///
+    /// let mut rust = Language::new();
+    /// rust.add_report(report.clone());
+    /// rust.total();
+    ///
+    /// let mut other = Language::new();
+    /// other.add_report(report);
+    /// other.total();
+    ///
+    /// rust.merge(other);
+    /// assert_eq!(rust.code, 10);
+    /// assert_eq!(rust.reports.len(), 1);
+    /// ```
+    pub fn merge(&mut self, other: Language) {
+        self.inaccurate |= other.inaccurate;
+
+        // The children of a replaced report are replaced along with it, even
+        // in languages the new report no longer has.
+        let incoming: HashSet<_> = other
+            .reports
+            .iter()
+            .map(|report| normalise(&report.path()))
+            .collect();
+        for reports in self.children.values_mut() {
+            reports.retain(|report| !incoming.contains(&normalise(&report.path())));
+        }
+        self.children.retain(|_, reports| !reports.is_empty());
+
+        merge_reports(&mut self.reports, other.reports);
+        for (language, reports) in other.children {
+            merge_reports(self.children.entry(language).or_default(), reports);
+        }
+
+        if let Some(summary) = other.summary {
+            *self.summary.get_or_insert_with(Summary::default) += summary;
+        }
+
+        self.total();
+    }
+
     /// Marks this language as possibly not reflecting correct stats.
     #[inline]
     pub fn mark_inaccurate(&mut self) {
@@ -72,12 +170,13 @@ impl Language {
     pub fn summarise(&self) -> Language {
         let mut summary = self.clone();
 
-        for reports in self.children.values() {
-            for stats in reports.iter().map(|r| r.stats.summarise()) {
-                summary.comments += stats.comments;
-                summary.code += stats.code;
-                summary.blanks += stats.blanks;
-            }
+        let children = self.children.values().flatten().map(|r| &r.stats);
+        let summarised = self.summary.iter().flat_map(|s| s.stats.blobs.values());
+
+        for stats in children.chain(summarised).map(CodeStats::summarise) {
+            summary.comments += stats.comments;
+            summary.code += stats.code;
+            summary.blanks += stats.blanks;
         }
 
         summary
@@ -105,10 +204,11 @@ impl Language {
         let mut code = 0;
         let mut comments = 0;
 
-        for report in &self.reports {
-            blanks += report.stats.blanks;
-            code += report.stats.code;
-            comments += report.stats.comments;
+        let summary = self.summary.iter().map(|s| &s.stats);
+        for stats in self.reports.iter().map(|r| &r.stats).chain(summary) {
+            blanks += stats.blanks;
+            code += stats.code;
+            comments += stats.comments;
         }
 
         self.blanks = blanks;
@@ -126,8 +226,62 @@ impl Language {
     /// assert!(rust.is_empty());
     /// ```
     #[must_use]
+    // `Option::is_none_or` would need Rust 1.82.
+    #[allow(clippy::unnecessary_map_or)]
     pub fn is_empty(&self) -> bool {
-        self.code == 0 && self.comments == 0 && self.blanks == 0 && self.children.is_empty()
+        self.code == 0
+            && self.comments == 0
+            && self.blanks == 0
+            && self.children.is_empty()
+            && self
+                .summary
+                .as_ref()
+                .map_or(true, |summary| summary.stats.blobs.is_empty())
+    }
+
+    /// How the lines of the language's files are distributed, counting the
+    /// lines of the languages embedded in them. Returns `None` if there
+    /// aren't any reports, such as when only a summary was kept.
+    ///
+    /// ```
+    /// # use tokei::*;
+    /// let mut rust = Language::new();
+    /// for (name, code) in [("a.rs", 10), ("b.rs", 30)] {
+    ///     let mut report = Report::new(name.into());
+    ///     report.stats.code = code;
+    ///     rust.add_report(report);
+    /// }
+    ///
+    /// let distribution = rust.distribution().unwrap();
+    /// assert_eq!(distribution.mean, 20.0);
+    /// assert_eq!(distribution.max, 30);
+    /// ```
+    #[must_use]
+    pub fn distribution(&self) -> Option<Distribution> {
+        Distribution::new(self.reports.iter().map(file_lines))
+    }
+
+    /// The `n` largest of the language's files by their lines, counting the
+    /// lines of the languages embedded in them, largest first.
+    ///
+    /// ```
+    /// # use tokei::*;
+    /// let mut rust = Language::new();
+    /// for (name, code) in [("a.rs", 10), ("b.rs", 30), ("c.rs", 20)] {
+    ///     let mut report = Report::new(name.into());
+    ///     report.stats.code = code;
+    ///     rust.add_report(report);
+    /// }
+    ///
+    /// let largest: Vec<_> = rust.largest(2).iter().map(|r| r.path()).collect();
+    /// assert_eq!(largest, [std::path::Path::new("b.rs"), std::path::Path::new("c.rs")]);
+    /// ```
+    #[must_use]
+    pub fn largest(&self, n: usize) -> Vec<&Report> {
+        let mut reports: Vec<_> = self.reports.iter().collect();
+        reports.sort_by_cached_key(|report| (Reverse(file_lines(report)), report.path()));
+        reports.truncate(n);
+        reports
     }
 
     /// Sorts each of the `Report`s contained in the language based
@@ -151,26 +305,62 @@ impl Language {
         match category {
             Sort::Blanks => self
                 .reports
-                .sort_by(|a, b| b.stats.blanks.cmp(&a.stats.blanks)),
+                .sort_by_key(|r| std::cmp::Reverse(r.stats.blanks)),
             Sort::Comments => self
                 .reports
-                .sort_by(|a, b| b.stats.comments.cmp(&a.stats.comments)),
-            Sort::Code => self.reports.sort_by(|a, b| b.stats.code.cmp(&a.stats.code)),
-            Sort::Files => self.reports.sort_by(|a, b| a.name.cmp(&b.name)),
+                .sort_by_key(|r| std::cmp::Reverse(r.stats.comments)),
+            Sort::Code => self
+                .reports
+                .sort_by_key(|r| std::cmp::Reverse(r.stats.code)),
+            Sort::Files => self.reports.sort_by_key(Report::path),
             Sort::Lines => self
                 .reports
-                .sort_by(|a, b| b.stats.lines().cmp(&a.stats.lines())),
+                .sort_by_key(|r| std::cmp::Reverse(r.stats.lines())),
         }
     }
 }
 
+/// The lines of a file, including the lines of the languages embedded in it.
+pub(crate) fn file_lines(report: &Report) -> usize {
+    report.stats.summarise().lines()
+}
+
+/// Adds `incoming` to `reports`, replacing any report with the same path,
+/// ignoring `.` components.
+fn merge_reports(reports: &mut Vec<Report>, incoming: Vec<Report>) {
+    let mut indices: HashMap<_, _> = reports
+        .iter()
+        .enumerate()
+        .map(|(i, report)| (normalise(&report.path()), i))
+        .collect();
+
+    for report in incoming {
+        let name = normalise(&report.path());
+
+        match indices.get(&name) {
+            Some(&i) => reports[i] = report,
+            None => {
+                indices.insert(name, reports.len());
+                reports.push(report);
+            }
+        }
+    }
+}
+
+/// Adds up two languages, keeping every report of both. See
+/// [`Language::merge`] to replace reports for the same files instead.
 impl AddAssign for Language {
     fn add_assign(&mut self, mut rhs: Self) {
         self.comments += rhs.comments;
         self.blanks += rhs.blanks;
         self.code += rhs.code;
         self.reports.extend(mem::take(&mut rhs.reports));
-        self.children.extend(mem::take(&mut rhs.children));
+        for (language, reports) in mem::take(&mut rhs.children) {
+            self.children.entry(language).or_default().extend(reports);
+        }
+        if let Some(summary) = rhs.summary.take() {
+            *self.summary.get_or_insert_with(Summary::default) += summary;
+        }
         self.inaccurate |= rhs.inaccurate;
     }
 }
@@ -1,15 +1,17 @@
-use std::sync::Arc;
+use std::{borrow::Cow, path::Path, sync::Arc};
 
 use aho_corasick::{AhoCorasick, AhoCorasickBuilder};
 use dashmap::DashMap;
 use grep_searcher::LineStep;
 use log::Level::Trace;
+use memchr::memchr;
 use once_cell::sync::Lazy;
 
-use super::embedding::{
-    RegexCache, RegexFamily, ENDING_MARKDOWN_REGEX, END_SCRIPT, END_STYLE, END_TEMPLATE,
+use super::{
+    classify::{Classification, LineKind, LineReason},
+    embedding::{self, BlockMatch, ClosingLine, Embedding, Fence, StartTag},
 };
-use crate::{stats::CodeStats, utils::ext::SliceExt, Config, LanguageType};
+use crate::{utils::ext::SliceExt, Config, LanguageType};
 
 /// Tracks the syntax of the language as well as the current state in the file.
 /// Current has what could be consider three types of mode.
@@ -29,107 +31,227 @@ pub(crate) struct SyntaxCounter {
     pub(crate) quote_is_doc_quote: bool,
     pub(crate) stack: Vec<&'static str>,
     pub(crate) quote_is_verbatim: bool,
+    /// Whether no lines of the file have been counted yet.
+    pub(crate) at_start_of_file: bool,
+    /// The path of the file, if the text is a file's, for the embedding
+    /// rules that only apply to some paths.
+    pub(crate) path: Option<Arc<Path>>,
+    /// Whether embedded languages are counted as the host's own text, for
+    /// lines held back by a block that stayed open for too long.
+    pub(crate) ignore_embeddings: bool,
 }
 
+/// A child language found inside the current line. `stats` holds the lines
+/// of `text[offset..end]` classified as `language`, after which the parent
+/// resumes at `end`. If the line that opened the child isn't part of the
+/// child itself, the parent counts it as `opening_line`. Likewise the parent
+/// counts the line starting at `closing_line`'s position, if any, as its
+/// kind.
 #[derive(Clone, Debug)]
-pub(crate) struct FileContext {
-    pub(crate) language: LanguageContext,
-    pub(crate) stats: CodeStats,
+pub(crate) struct FileContext<S> {
+    pub(crate) language: LanguageType,
+    pub(crate) opening_line: Option<LineKind>,
+    pub(crate) closing_line: Option<(usize, LineKind)>,
+    pub(crate) stats: S,
+    pub(crate) offset: usize,
     pub(crate) end: usize,
 }
 
-impl FileContext {
-    pub fn new(language: LanguageContext, end: usize, stats: CodeStats) -> Self {
+impl<S> FileContext<S> {
+    pub fn new(
+        language: LanguageType,
+        opening_line: Option<LineKind>,
+        offset: usize,
+        end: usize,
+        stats: S,
+    ) -> Self {
         Self {
             language,
+            opening_line,
+            closing_line: None,
             stats,
+            offset,
             end,
         }
     }
 }
 
-#[derive(Clone, Debug)]
-pub(crate) enum LanguageContext {
-    Html {
-        language: LanguageType,
-    },
-    Markdown {
-        balanced: bool,
-        language: LanguageType,
-    },
-    Rust,
+/// What the text a [`SharedMatchers`] counts is to the file it's in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Role {
+    /// The text is the file, or a child language's code.
+    Code,
+    /// The text is the code of a language embedded in its host, such as the
+    /// PHP in a PHP file.
+    Guest,
+    /// The text is documentation written in the language, such as the XML
+    /// of a C# doc comment, so every non-blank line is a comment.
+    Documentation,
 }
 
 #[derive(Clone, Debug)]
 pub(crate) struct SharedMatchers {
-    pub language: LanguageType,
+    /// The language embedded in a host whose code has to be counted with its
+    /// own syntax rather than these matchers, if any.
+    pub guest: Option<LanguageType>,
     pub allows_nested: bool,
     pub doc_quotes: &'static [(&'static str, &'static str)],
+    pub embedding: Vec<Embedding>,
     pub important_syntax: AhoCorasick<u16>,
     #[allow(dead_code)]
     pub any_comments: &'static [&'static str],
     pub is_fortran: bool,
     pub is_literate: bool,
-    pub line_comments: &'static [&'static str],
-    pub any_multi_line_comments: &'static [(&'static str, &'static str)],
-    pub multi_line_comments: &'static [(&'static str, &'static str)],
+    pub line_comments: Vec<&'static str>,
+    pub any_multi_line_comments: Vec<(&'static str, &'static str)>,
+    pub multi_line_comments: Vec<(&'static str, &'static str)>,
     pub nested_comments: &'static [(&'static str, &'static str)],
     pub string_literals: &'static [(&'static str, &'static str)],
     pub verbatim_string_literals: &'static [(&'static str, &'static str)],
 }
 
 impl SharedMatchers {
-    pub fn new(language: LanguageType) -> Arc<Self> {
-        static MATCHERS: Lazy<DashMap<LanguageType, Arc<SharedMatchers>>> = Lazy::new(DashMap::new);
+    /// The matchers for counting `language` in `role`. A language with a
+    /// host is counted with the host's syntax and embedding rules along with
+    /// its own embedding rules, unless it's counted as a `Role::Guest`, in
+    /// which case it is counted with its own syntax alone.
+    pub fn new(language: LanguageType, role: Role) -> Arc<Self> {
+        static MATCHERS: Lazy<DashMap<(LanguageType, Role), Arc<SharedMatchers>>> =
+            Lazy::new(DashMap::new);
+
+        let role = match role {
+            Role::Guest if language.host().is_none() => Role::Code,
+            role => role,
+        };
 
         MATCHERS
-            .entry(language)
-            .or_insert_with(|| Arc::new(Self::init(language)))
+            .entry((language, role))
+            .or_insert_with(|| Arc::new(Self::init(language, role)))
             .value()
             .clone()
     }
 
-    pub fn init(language: LanguageType) -> Self {
+    pub fn init(language: LanguageType, role: Role) -> Self {
         fn init_corasick(pattern: &[&'static str], anchored: bool) -> AhoCorasick<u16> {
             let mut builder = AhoCorasickBuilder::new();
             builder.anchored(anchored).dfa(true).prefilter(true);
             builder.build_with_size(pattern).unwrap()
         }
 
+        let as_guest = role == Role::Guest;
+        let host = language.host().filter(|_| !as_guest);
+        let syntax = host.unwrap_or(language);
+        let mut rules = if as_guest {
+            Vec::new()
+        } else {
+            syntax.embedding().to_vec()
+        };
+        let mut important_syntax = syntax.important_syntax().to_vec();
+        let mut line_comments = syntax.line_comments().to_vec();
+        let mut multi_line_comments = syntax.multi_line_comments().to_vec();
+        let mut any_multi_line_comments = syntax.any_multi_line_comments().to_vec();
+        let mut guest = None;
+
+        if host.is_some() {
+            rules.extend_from_slice(language.embedding());
+            important_syntax.extend(language.embedding().iter().flat_map(|r| r.triggers()));
+
+            // When the code embedded in the host is the language itself, its
+            // syntax only applies to that code. Otherwise it's the syntax of
+            // the file, such as server side comments.
+            if language
+                .embedding()
+                .iter()
+                .any(|r| r.default_language() == Some(language))
+            {
+                guest = Some(language);
+            } else {
+                line_comments.extend_from_slice(language.line_comments());
+                multi_line_comments.extend_from_slice(language.multi_line_comments());
+                any_multi_line_comments.extend_from_slice(language.multi_line_comments());
+                important_syntax.extend(language.multi_line_comments().iter().map(|c| c.0));
+            }
+        }
+
         Self {
-            language,
-            allows_nested: language.allows_nested(),
-            doc_quotes: language.doc_quotes(),
-            is_fortran: language.is_fortran(),
-            is_literate: language.is_literate(),
-            important_syntax: init_corasick(language.important_syntax(), false),
-            any_comments: language.any_comments(),
-            line_comments: language.line_comments(),
-            multi_line_comments: language.multi_line_comments(),
-            any_multi_line_comments: language.any_multi_line_comments(),
-            nested_comments: language.nested_comments(),
-            string_literals: language.quotes(),
-            verbatim_string_literals: language.verbatim_quotes(),
+            guest,
+            allows_nested: syntax.allows_nested(),
+            doc_quotes: syntax.doc_quotes(),
+            embedding: rules.into_iter().map(Embedding::compile).collect(),
+            is_fortran: syntax.is_fortran(),
+            is_literate: syntax.is_literate() || role == Role::Documentation,
+            important_syntax: init_corasick(&important_syntax, false),
+            any_comments: syntax.any_comments(),
+            line_comments,
+            multi_line_comments,
+            any_multi_line_comments,
+            nested_comments: syntax.nested_comments(),
+            string_literals: syntax.quotes(),
+            verbatim_string_literals: syntax.verbatim_quotes(),
+        }
+    }
+
+    /// Classifies a line that doesn't contain any important syntax.
+    pub fn classify_plain_line(&self, line: &[u8]) -> (LineKind, LineReason) {
+        // FORTRAN has a rule where it only counts as a comment if it's the
+        // first character in the column, so removing starting whitespace
+        // could cause a miscount.
+        let line = if self.is_fortran { line } else { line.trim() };
+
+        if line.trim().is_empty() {
+            (LineKind::Blank, LineReason::Blank)
+        } else if self.is_literate
+            || self
+                .line_comments
+                .iter()
+                .any(|c| line.starts_with(c.as_bytes()))
+        {
+            (LineKind::Comment, LineReason::FastPath)
+        } else {
+            (LineKind::Code, LineReason::FastPath)
         }
     }
 }
 
 #[derive(Debug)]
-pub(crate) enum AnalysisReport {
+pub(crate) enum AnalysisReport<S> {
     /// No child languages were found, contains a boolean representing whether
     /// the line ended with comments or not.
     Normal(bool),
-    ChildLanguage(FileContext),
+    ChildLanguage(FileContext<S>),
+    /// The line opens a block of text in no known language, which runs up to
+    /// the given position and is counted as plain lines.
+    PlainText(usize),
+    /// The line opens a child language that may continue past the end of the
+    /// text read so far, so it has to be analysed again with more text.
+    Incomplete,
 }
 
 impl SyntaxCounter {
     pub(crate) fn new(language: LanguageType) -> Self {
+        Self::with_matchers(SharedMatchers::new(language, Role::Code))
+    }
+
+    /// A counter for the code of `language` embedded in its host.
+    pub(crate) fn guest(language: LanguageType) -> Self {
+        Self::with_matchers(SharedMatchers::new(language, Role::Guest))
+    }
+
+    /// A counter for documentation written in `language`.
+    pub(crate) fn documentation(language: LanguageType) -> Self {
+        Self::with_matchers(SharedMatchers::new(language, Role::Documentation))
+    }
+
+    fn with_matchers(shared: Arc<SharedMatchers>) -> Self {
         Self {
-            shared: SharedMatchers::new(language),
+            shared,
             quote_is_doc_quote: false,
             quote_is_verbatim: false,
             stack: Vec::with_capacity(1),
             quote: None,
+            at_start_of_file: true,
+            path: None,
+            ignore_embeddings: false,
         }
     }
 
@@ -166,48 +288,32 @@ impl SyntaxCounter {
     }
 
     /// Try to see if we can determine what a line is from examining the whole
-    /// line at once. Returns `true` if successful.
+    /// line at once.
     pub(crate) fn try_perform_single_line_analysis(
         &self,
         line: &[u8],
-        stats: &mut crate::stats::CodeStats,
-    ) -> bool {
+    ) -> Option<(LineKind, LineReason)> {
         if !self.is_plain_mode() {
-            false
+            None
         } else if line.trim().is_empty() {
-            stats.blanks += 1;
-            trace!("Blank No.{}", stats.blanks);
-            true
+            trace!("Blank");
+            Some((LineKind::Blank, LineReason::Blank))
         } else if self.shared.important_syntax.is_match(line) {
-            false
+            None
         } else {
             trace!("^ Skippable");
-
-            if self.shared.is_literate
-                || self
-                    .shared
-                    .line_comments
-                    .iter()
-                    .any(|c| line.starts_with(c.as_bytes()))
-            {
-                stats.comments += 1;
-                trace!("Comment No.{}", stats.comments);
-            } else {
-                stats.code += 1;
-                trace!("Code No.{}", stats.code);
-            }
-
-            true
+            Some(self.shared.classify_plain_line(line))
         }
     }
 
-    pub(crate) fn perform_multi_line_analysis(
+    pub(crate) fn perform_multi_line_analysis<S: Classification>(
         &mut self,
         lines: &[u8],
         start: usize,
         end: usize,
         config: &Config,
-    ) -> AnalysisReport {
+        eof: bool,
+    ) -> AnalysisReport<S> {
         let mut ended_with_comments = false;
         let mut skip = 0;
         macro_rules! skip {
@@ -216,7 +322,22 @@ impl SyntaxCounter {
             }};
         }
 
-        let regex_cache = RegexCache::build(self.shared.language, lines, start, end);
+        let matches = if self.ignore_embeddings {
+            Vec::new()
+        } else {
+            match embedding::match_line(
+                &self.shared.embedding,
+                lines,
+                start,
+                end,
+                self.path.as_deref(),
+                self.at_start_of_file && start == 0,
+                eof,
+            ) {
+                Some(matches) => matches,
+                None => return AnalysisReport::Incomplete,
+            }
+        };
 
         for i in start..end {
             if skip != 0 {
@@ -243,8 +364,8 @@ impl SyntaxCounter {
                 continue;
             }
 
-            if let Some(child) = self.parse_context(lines, i, end, config, &regex_cache) {
-                return AnalysisReport::ChildLanguage(child);
+            if let Some(report) = self.parse_context(lines, start, i, end, config, &matches, eof) {
+                return report;
             }
 
             let is_quote_or_multi_line = self
@@ -274,9 +395,9 @@ impl SyntaxCounter {
     ///  1. Check if string literal is a doc string and whether tokei has
     ///     been configured to treat them as comments.
     ///     - Yes: When the line starts with the doc string or when we are
-    ///            continuing from a previous line.
+    ///       continuing from a previous line.
     ///  - No: The string is a normal string literal or tokei isn't
-    ///        configured to count them as comments.
+    ///    configured to count them as comments.
     /// 2. If we're not in string mode, check if we left it this on this line.
     ///    - Yes: When we found a doc quote and we started in comments.
     /// 3. Yes: When the whole line is a comment e.g. `/* hello */`
@@ -289,7 +410,8 @@ impl SyntaxCounter {
         config: &crate::Config,
         _ended_with_comments: bool,
         started_in_comments: bool,
-    ) -> bool {
+        starting_depth: usize,
+    ) -> Option<LineReason> {
         let trimmed = line.trim();
         let whole_line_is_comment = || {
             self.shared
@@ -315,15 +437,16 @@ impl SyntaxCounter {
                 .iter()
                 .any(|(start, end)| end == quote && trimmed.starts_with(start.as_bytes()))
         };
+        let multi_line_comment = || LineReason::MultiLineComment {
+            depth: starting_depth.max(self.stack.len()),
+        };
 
         // `Some(true)` in order to respect the current configuration.
-        #[allow(clippy::if_same_then_else)]
         if self.quote.is_some() {
             if self.quote_is_doc_quote && config.treat_doc_strings_as_comments == Some(true) {
-                self.quote.map_or(false, |q| line.starts_with(q.as_bytes()))
-                    || (self.quote.is_some())
+                Some(LineReason::DocString)
             } else {
-                false
+                None
             }
         } else if self
             .shared
@@ -332,188 +455,337 @@ impl SyntaxCounter {
             .any(|(_, e)| line.contains_slice(e.as_bytes()))
             && started_in_comments
         {
-            true
+            Some(LineReason::DocString)
         } else if (whole_line_is_comment)() {
-            true
-        } else if started_in_comments {
-            true
+            Some(LineReason::WholeLineComment)
+        } else if started_in_comments || (starts_with_comment)() {
+            Some((multi_line_comment)())
         } else {
-            (starts_with_comment)()
+            None
         }
     }
 
+    /// Looks for a child language starting at `start`, on the line that
+    /// begins at `line_start` and ends at `end`. Unless `eof` is set, `lines`
+    /// may be followed by more text, so a child that runs up to the end of
+    /// `lines` is reported as `AnalysisReport::Incomplete`.
+    #[allow(clippy::too_many_arguments)]
     #[inline]
-    pub(crate) fn parse_context(
+    pub(crate) fn parse_context<S: Classification>(
         &mut self,
         lines: &[u8],
+        line_start: usize,
         start: usize,
         end: usize,
         config: &Config,
-        regex_cache: &RegexCache,
-    ) -> Option<FileContext> {
-        use std::str::FromStr;
+        matches: &[BlockMatch],
+        eof: bool,
+    ) -> Option<AnalysisReport<S>> {
+        if self.ignore_embeddings || self.quote.is_some() || !self.stack.is_empty() {
+            return None;
+        }
 
-        // static TYPE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r#"type="(.*)".*>"#).unwrap());
-        if self.quote.is_some() || !self.stack.is_empty() {
+        if let Some(block) = matches.iter().find(|m| m.start == start) {
+            return self.parse_block(lines, end, block, config, eof);
+        }
+
+        // Fences and prefixed blocks only count when they start the line.
+        if !lines[line_start..start].trim().is_empty() {
             return None;
         }
 
-        match regex_cache.family()? {
-            RegexFamily::Markdown(md) => {
-                if !lines[start..end].contains_slice(b"```") {
-                    return None;
-                }
+        let fence = self.shared.embedding.iter().find_map(|rule| match rule {
+            Embedding::Fence { fences, delimiters } => {
+                Fence::open(&lines[line_start..end], fences).map(|fence| (fence, *delimiters))
+            }
+            _ => None,
+        });
+        if let Some((fence, delimiters)) = fence {
+            return Some(self.parse_fence(lines, end, &fence, delimiters, config, eof));
+        }
 
-                let opening_fence = md.starts_in_range(start, end)?;
-                let start_of_code = opening_fence.end();
-                let closing_fence = ENDING_MARKDOWN_REGEX.find(&lines[start_of_code..]);
-                if let Some(m) = &closing_fence {
-                    trace!("{:?}", String::from_utf8_lossy(m.as_bytes()));
-                }
-                let end_of_code = closing_fence
-                    .map_or_else(|| lines.len(), |fence| start_of_code + fence.start());
-                let end_of_code_block =
-                    closing_fence.map_or_else(|| lines.len(), |fence| start_of_code + fence.end());
-                let balanced = closing_fence.is_some();
-                let identifier = &opening_fence.as_bytes().trim()[3..];
-
-                let language = identifier
-                    .split(|&b| b == b',')
-                    .find_map(|s| LanguageType::from_str(&String::from_utf8_lossy(s)).ok())?;
-                trace!(
-                    "{} BLOCK: {:?}",
+        let rest = lines[start..end].trim_start();
+        let (prefix, language, is_documentation) =
+            self.shared.embedding.iter().find_map(|rule| match rule {
+                Embedding::Prefix {
+                    prefixes,
                     language,
-                    String::from_utf8_lossy(&lines[start_of_code..end_of_code])
-                );
-                let stats =
-                    language.parse_from_slice(&lines[start_of_code..end_of_code].trim(), config);
-
-                Some(FileContext::new(
-                    LanguageContext::Markdown { balanced, language },
-                    end_of_code_block,
-                    stats,
-                ))
+                    is_documentation,
+                } => prefixes
+                    .iter()
+                    .find(|p| rest.starts_with(p.as_bytes()))
+                    .map(|p| (p.as_bytes(), *language, *is_documentation)),
+                _ => None,
+            })?;
+
+        let mut stepper = LineStep::new(b'\n', start, lines.len());
+        let mut text = Vec::new();
+        let mut end_of_block = None;
+
+        while let Some((start, end)) = stepper.next(lines) {
+            let line = &lines[start..end];
+            if line.trim_start().starts_with(prefix) {
+                trace!("{}", String::from_utf8_lossy(line));
+                // Blank out the prefix rather than removing it so that the
+                // child's text lines up with the source.
+                let prefix_start = text.len() + line.len() - line.trim_start().len();
+                text.extend_from_slice(line);
+                text[prefix_start..prefix_start + prefix.len()].fill(b' ');
+            } else {
+                end_of_block = Some(start);
+                break;
             }
-            RegexFamily::Rust => {
-                let rest = &lines[start..];
-                let comment_syntax = if rest.trim_start().starts_with(b"///") {
-                    b"///"
-                } else if rest.trim_start().starts_with(b"//!") {
-                    b"//!"
-                } else {
-                    return None;
-                };
+        }
 
-                let mut stepper = LineStep::new(b'\n', start, lines.len());
-                let mut markdown = Vec::new();
-                let mut end_of_block = lines.len();
-
-                while let Some((start, end)) = stepper.next(lines) {
-                    if lines[start..].trim().starts_with(comment_syntax) {
-                        trace!("{}", String::from_utf8_lossy(&lines[start..end]));
-                        let line = lines[start..end].trim_start();
-                        let stripped_line = &line[3.min(line.len())..];
-                        markdown.extend_from_slice(stripped_line);
-                        end_of_block = end;
-                    } else {
-                        end_of_block = start;
-                        break;
-                    }
-                }
+        let end_of_block = match end_of_block {
+            Some(end_of_block) => end_of_block,
+            None if eof => lines.len(),
+            None => return Some(AnalysisReport::Incomplete),
+        };
 
-                trace!("Markdown found: {:?}", String::from_utf8_lossy(&markdown));
-                let doc_block = LanguageType::Markdown.parse_from_slice(markdown.trim(), config);
+        trace!("{} found: {:?}", language, String::from_utf8_lossy(&text));
+        let stats = if is_documentation {
+            language.classify_as_documentation(&text, config)
+        } else {
+            language.classify(&text, config)
+        };
+
+        Some(AnalysisReport::ChildLanguage(FileContext::new(
+            language,
+            None,
+            start,
+            end_of_block,
+            stats,
+        )))
+    }
 
-                Some(FileContext::new(
-                    LanguageContext::Rust,
-                    end_of_block,
-                    doc_block,
-                ))
+    /// Parses the code block opened by `fence` on the line ending at `end`,
+    /// which runs until the line closing it or the end of the file.
+    fn parse_fence<S: Classification>(
+        &self,
+        lines: &[u8],
+        end: usize,
+        fence: &Fence,
+        delimiters: LineKind,
+        config: &Config,
+        eof: bool,
+    ) -> AnalysisReport<S> {
+        let mut stepper = LineStep::new(b'\n', end, lines.len());
+        let mut closing_line = None;
+
+        while let Some((start, line_end)) = stepper.next(lines) {
+            if fence.is_closed_by(&lines[start..line_end]) {
+                closing_line = Some((start, line_end));
+                break;
             }
-            RegexFamily::HtmlLike(html) => {
-                if let Some(mut captures) = html.start_script_in_range(start, end) {
-                    let start_of_code = captures.next().unwrap().end();
-                    let closing_tag = END_SCRIPT.find(&lines[start_of_code..])?;
-                    let end_of_code = start_of_code + closing_tag.start();
-                    let language = captures
-                        .next()
-                        .and_then(|m| {
-                            LanguageType::from_mime(&String::from_utf8_lossy(m.as_bytes().trim()))
-                        })
-                        .unwrap_or(LanguageType::JavaScript);
-                    let script_contents = &lines[start_of_code..end_of_code];
-                    if script_contents.trim().is_empty() {
-                        return None;
-                    }
+        }
 
-                    let stats = language.parse_from_slice(
-                        script_contents.trim_first_and_last_line_of_whitespace(),
-                        config,
-                    );
-                    Some(FileContext::new(
-                        LanguageContext::Html { language },
-                        end_of_code,
-                        stats,
-                    ))
-                } else if let Some(mut captures) = html.start_style_in_range(start, end) {
-                    let start_of_code = captures.next().unwrap().end();
-                    let closing_tag = END_STYLE.find(&lines[start_of_code..])?;
-                    let end_of_code = start_of_code + closing_tag.start();
-                    let language = captures
-                        .next()
-                        .and_then(|m| {
-                            LanguageType::from_str(
-                                &String::from_utf8_lossy(m.as_bytes().trim()).to_lowercase(),
-                            )
-                            .ok()
-                        })
-                        .unwrap_or(LanguageType::Css);
-                    let style_contents = &lines[start_of_code..end_of_code];
-                    if style_contents.trim().is_empty() {
-                        return None;
-                    }
+        // The closing line has to be read in full before it can be skipped.
+        let (end_of_code, resume) = match closing_line {
+            Some((start, line_end)) if eof || lines[line_end - 1] == b'\n' => (start, line_end),
+            None if eof => (lines.len(), lines.len()),
+            _ => return AnalysisReport::Incomplete,
+        };
 
-                    let stats = language.parse_from_slice(
-                        style_contents.trim_first_and_last_line_of_whitespace(),
-                        config,
-                    );
-                    Some(FileContext::new(
-                        LanguageContext::Html { language },
-                        end_of_code,
-                        stats,
-                    ))
-                } else if let Some(mut captures) = html.start_template_in_range(start, end) {
-                    let start_of_code = captures.next().unwrap().end();
-                    let closing_tag = END_TEMPLATE.find(&lines[start_of_code..])?;
-                    let end_of_code = start_of_code + closing_tag.start();
-                    let language = captures
-                        .next()
-                        .and_then(|m| {
-                            LanguageType::from_str(
-                                &String::from_utf8_lossy(m.as_bytes().trim()).to_lowercase(),
-                            )
-                            .ok()
-                        })
-                        .unwrap_or(LanguageType::Html);
-
-                    let template_contents = &lines[start_of_code..end_of_code];
-                    if template_contents.trim().is_empty() {
-                        return None;
-                    }
-                    let stats = language.parse_from_slice(
-                        template_contents.trim_first_and_last_line_of_whitespace(),
-                        config,
-                    );
-                    Some(FileContext::new(
-                        LanguageContext::Html { language },
-                        end_of_code,
-                        stats,
-                    ))
+        let language = match fence.language {
+            Some(language) => language,
+            None => return AnalysisReport::PlainText(resume),
+        };
+        trace!(
+            "{} FENCE: {:?}",
+            language,
+            String::from_utf8_lossy(&lines[end..end_of_code])
+        );
+        let stats = language.classify(&lines[end..end_of_code], config);
+
+        let mut context = FileContext::new(language, Some(delimiters), end, resume, stats);
+        if resume != end_of_code {
+            context.closing_line = Some((end_of_code, delimiters));
+        }
+
+        AnalysisReport::ChildLanguage(context)
+    }
+
+    /// Parses the block opened by `block` on the line ending at `end`. The
+    /// child's text runs from the end of that line to the start of the line
+    /// the block ends on, which is left for the parent to count.
+    fn parse_block<S: Classification>(
+        &self,
+        lines: &[u8],
+        end: usize,
+        block: &BlockMatch,
+        config: &Config,
+        eof: bool,
+    ) -> Option<AnalysisReport<S>> {
+        let rule = &self.shared.embedding[block.rule];
+        let (delimiters, runs_to_end_of_file, opening_line_is_child, closing_line, line_prefix) =
+            match rule {
+                Embedding::Block {
+                    doc_strings_as_comments: Some(as_comments),
+                    ..
+                } if (config.treat_doc_strings_as_comments == Some(true)) != *as_comments => {
+                    return None
+                }
+                Embedding::Block {
+                    delimiters,
+                    runs_to_end_of_file,
+                    opening_line_is_child,
+                    closing_line,
+                    line_prefix,
+                    ..
+                } => (
+                    *delimiters,
+                    *runs_to_end_of_file,
+                    *opening_line_is_child,
+                    *closing_line,
+                    *line_prefix,
+                ),
+                Embedding::Element { delimiters, .. } => {
+                    (*delimiters, false, false, ClosingLine::Host, None)
+                }
+                Embedding::TaggedTemplate { delimiters, .. } => {
+                    (*delimiters, false, false, ClosingLine::Delimiters, None)
+                }
+                _ => return None,
+            };
+
+        // The child starts after the last line the start matched.
+        let child_start = if opening_line_is_child {
+            line_start(lines, block.start)
+        } else if block.end > end {
+            memchr(b'\n', &lines[block.end - 1..]).map_or(lines.len(), |i| block.end + i)
+        } else {
+            end
+        };
+        let tag = match rule {
+            Embedding::Element { .. } => Some(StartTag::parse(&lines[block.start..])?),
+            _ => None,
+        };
+        let language = match rule {
+            Embedding::Block {
+                capture, default, ..
+            } => {
+                let captured = block.capture.clone().map(|range| &lines[range]);
+                embedding::child_language(captured, *capture, *default)?
+            }
+            Embedding::Element {
+                tag: Some(_),
+                default,
+                ..
+            } => tag.as_ref()?.language().or(*default)?,
+            // Custom blocks are only counted when they've been declared.
+            Embedding::Element { tag: None, .. } => {
+                let tag = tag.as_ref()?;
+                let declared = config
+                    .custom_blocks
+                    .as_ref()?
+                    .get(&*String::from_utf8_lossy(tag.name))?;
+                tag.language().unwrap_or(*declared)
+            }
+            Embedding::TaggedTemplate { tags, .. } => {
+                let tag = &lines[block.capture.clone()?];
+                embedding::template_language(tag, tags, config)?
+            }
+            _ => return None,
+        };
+        let closing = match (rule, &tag) {
+            (
+                Embedding::Block {
+                    end: Some(pattern), ..
+                },
+                _,
+            ) => pattern
+                .find(&lines[block.end..])
+                .map(|closing| (block.end + closing.start(), block.end + closing.end())),
+            (_, Some(tag)) => embedding::find_closing_tag(lines, block.end, tag.name),
+            (Embedding::TaggedTemplate { .. }, _) => {
+                embedding::find_template_end(lines, block.end).map(|end| (end, end + 1))
+            }
+            _ => {
+                let opening = &lines[line_start(lines, block.start)..end];
+                let indent = opening.len() - opening.trim_start().len();
+                find_dedent(lines, child_start, indent, eof).map(|end| (end, end))
+            }
+        };
+
+        let (end_of_code, resume) = match closing {
+            Some((closing_start, closing_end)) => {
+                // A block ending on a dedent can end with the file.
+                let end_of_code = if closing_start == lines.len() {
+                    closing_start
                 } else {
-                    None
+                    line_start(lines, closing_start)
+                };
+                let end_of_line = match memchr(b'\n', &lines[closing_end..]) {
+                    Some(i) => closing_end + i + 1,
+                    None if closing_line != ClosingLine::Host && !eof => {
+                        return Some(AnalysisReport::Incomplete)
+                    }
+                    None => lines.len(),
+                };
+
+                match closing_line {
+                    ClosingLine::Host => (end_of_code, end_of_code),
+                    ClosingLine::Delimiters => (end_of_code, end_of_line),
+                    ClosingLine::Child => (end_of_line, end_of_line),
                 }
             }
+            None if !eof => return Some(AnalysisReport::Incomplete),
+            None if runs_to_end_of_file => (lines.len(), lines.len()),
+            None => return None,
+        };
+
+        if end_of_code <= child_start || lines[child_start..end_of_code].trim().is_empty() {
+            return None;
         }
+
+        if let Embedding::Block {
+            first_line: Some(pattern),
+            ..
+        } = rule
+        {
+            let first_line = lines[child_start..end_of_code]
+                .split(|&c| c == b'\n')
+                .find(|line| !line.trim().is_empty());
+            if !first_line.is_some_and(|line| pattern.is_match(line)) {
+                return None;
+            }
+        }
+
+        trace!(
+            "{} BLOCK: {:?}",
+            language,
+            String::from_utf8_lossy(&lines[child_start..end_of_code])
+        );
+        let mut text = match line_prefix {
+            Some(prefix) => Cow::Owned(blank_line_prefixes(
+                &lines[child_start..end_of_code],
+                prefix,
+            )),
+            None => Cow::Borrowed(&lines[child_start..end_of_code]),
+        };
+        // Blank out the start rather than removing it so that the child's
+        // text lines up with the source.
+        if opening_line_is_child {
+            text.to_mut()[..block.end - child_start].fill(b' ');
+        }
+        let stats = if self.shared.guest == Some(language) {
+            language.classify_as_guest(&text, config)
+        } else {
+            language.classify(&text, config)
+        };
+
+        let opening_line = if opening_line_is_child {
+            None
+        } else {
+            Some(delimiters)
+        };
+        let mut context = FileContext::new(language, opening_line, child_start, resume, stats);
+        if resume != end_of_code {
+            context.closing_line = Some((end_of_code, delimiters));
+        }
+
+        Some(AnalysisReport::ChildLanguage(context))
     }
 
     #[inline]
@@ -596,7 +868,7 @@ impl SyntaxCounter {
             .shared
             .multi_line_comments
             .iter()
-            .chain(self.shared.nested_comments);
+            .chain(self.shared.nested_comments.iter());
         for &(start, end) in iter {
             if window.starts_with(start.as_bytes()) {
                 if self.stack.is_empty()
@@ -624,7 +896,7 @@ impl SyntaxCounter {
         if self
             .stack
             .last()
-            .map_or(false, |l| window.starts_with(l.as_bytes()))
+            .is_some_and(|l| window.starts_with(l.as_bytes()))
         {
             let last = self.stack.pop().unwrap();
 
@@ -642,3 +914,52 @@ impl SyntaxCounter {
         }
     }
 }
+
+/// Where a block of lines indented by more than `indent` starting at `start`
+/// ends, which is before the first line that isn't blank and is indented by
+/// no more than `indent`, or at the end of `lines` if `eof` is set. Blank
+/// lines at the end of the block are left out of it.
+fn find_dedent(lines: &[u8], start: usize, indent: usize, eof: bool) -> Option<usize> {
+    let mut stepper = LineStep::new(b'\n', start, lines.len());
+    let mut end_of_block = start;
+
+    while let Some((start, end)) = stepper.next(lines) {
+        let line = &lines[start..end];
+        let trimmed = line.trim_start();
+
+        if trimmed.is_empty() {
+            continue;
+        } else if line.len() - trimmed.len() <= indent {
+            return Some(end_of_block);
+        }
+        end_of_block = end;
+    }
+
+    Some(end_of_block).filter(|_| eof)
+}
+
+/// A copy of `text` with `prefix` blanked out wherever it starts a line, so
+/// that the copy still lines up with `text`.
+fn blank_line_prefixes(text: &[u8], prefix: &str) -> Vec<u8> {
+    let mut text = text.to_vec();
+    let mut stepper = LineStep::new(b'\n', 0, text.len());
+
+    while let Some((start, end)) = stepper.next(&text) {
+        let line = &text[start..end];
+        let prefix_start = start + line.len() - line.trim_start().len();
+
+        if text[prefix_start..end].starts_with(prefix.as_bytes()) {
+            text[prefix_start..prefix_start + prefix.len()].fill(b' ');
+        }
+    }
+
+    text
+}
+
+/// The position of the start of the line containing `position`.
+fn line_start(lines: &[u8], position: usize) -> usize {
+    lines[..position]
+        .iter()
+        .rposition(|&c| c == b'\n')
+        .map_or(0, |i| i + 1)
+}
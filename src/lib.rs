@@ -50,13 +50,18 @@ extern crate serde;
 #[macro_use]
 mod utils;
 mod config;
+mod groups;
 mod language;
+mod owners;
 mod sort;
 mod stats;
 
 pub use self::{
-    config::Config,
-    language::{Language, LanguageType, Languages},
+    config::{Config, EncodingOverrides},
+    groups::Groups,
+    language::{ClassifiedLine, Language, LanguageType, Languages, LineKind, LineReason},
+    owners::CodeOwners,
     sort::Sort,
-    stats::{find_char_boundary, CodeStats, Report},
+    stats::{find_char_boundary, CodeStats, Distribution, LineEnding, Report, Summary},
+    utils::encoding::read_file,
 };
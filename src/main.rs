@@ -3,16 +3,18 @@ extern crate log;
 
 mod cli;
 mod cli_utils;
+mod diff;
 mod input;
+mod schema;
 
-use std::{error::Error, io, process};
+use std::{borrow::Cow, cmp::Reverse, error::Error, io, path::Path, process};
 
-use tokei::{Config, Languages, Sort};
+use tokei::{CodeOwners, Config, Language, LanguageType, Languages, Sort};
 
 use crate::{
-    cli::Cli,
-    cli_utils::{Printer, FALLBACK_ROW_LEN},
-    input::add_input,
+    cli::{Cli, GroupBy},
+    cli_utils::{print_explanation, Printer, FALLBACK_ROW_LEN},
+    input::{add_input, read_input, Envelope, LanguageMap, Metadata, Output},
 };
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -23,10 +25,65 @@ fn main() -> Result<(), Box<dyn Error>> {
         process::exit(0);
     }
 
+    if cli.print_schema {
+        println!("{:#}", schema::output_schema());
+        process::exit(0);
+    }
+
     let config = cli.override_config(Config::from_config_files());
+
+    let columns = cli
+        .columns
+        .or(config.columns)
+        .or_else(|| {
+            if cli.files {
+                term_size::dimensions().map(|(w, _)| w)
+            } else {
+                None
+            }
+        })
+        .unwrap_or(FALLBACK_ROW_LEN)
+        .max(FALLBACK_ROW_LEN);
+
+    if let Some(path) = cli.explain() {
+        let language = match LanguageType::from_path(path, &config) {
+            Some(language) => language,
+            None => {
+                eprintln!("Error: Couldn't determine the language of '{}'.", path);
+                process::exit(1);
+            }
+        };
+
+        let text = match tokei::read_file(path, &config) {
+            Ok(text) => text,
+            Err(error) => {
+                eprintln!("Error reading {}:\n{}", path, error);
+                process::exit(1);
+            }
+        };
+
+        let mut writer = io::BufWriter::new(io::stdout());
+        print_explanation(&mut writer, language, Path::new(path), &text, &config)?;
+        return Ok(());
+    }
+
+    if let Some((old, new)) = cli.diff() {
+        let (old, new) = (read_input_or_exit(old), read_input_or_exit(new));
+        let mut printer = Printer::new(
+            columns,
+            cli.files,
+            io::BufWriter::new(io::stdout()),
+            cli.number_format,
+        );
+
+        printer.print_header("Language")?;
+        printer.print_diff(&diff::diff(&old, &new))?;
+        return Ok(());
+    }
+
     let mut languages = Languages::new();
 
-    if let Some(input) = cli.file_input() {
+    for input in cli.file_input() {
         if !add_input(input, &mut languages) {
             Cli::print_input_parse_failure(input);
             process::exit(1);
@@ -42,18 +99,14 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
     }
 
-    let columns = cli
-        .columns
-        .or(config.columns)
-        .or_else(|| {
-            if cli.files {
-                term_size::dimensions().map(|(w, _)| w)
-            } else {
-                None
-            }
-        })
-        .unwrap_or(FALLBACK_ROW_LEN)
-        .max(FALLBACK_ROW_LEN);
+    let owners = match cli.group_by {
+        Some(GroupBy::Owner) => Some(find_owners_or_exit(&input)),
+        Some(GroupBy::Group) if config.groups.is_none() => {
+            eprintln!("Error: No groups are defined in the config files.");
+            process::exit(1);
+        }
+        _ => None,
+    };
 
     if cli.streaming == Some(crate::cli::Streaming::Simple) {
         println!(
@@ -71,13 +124,41 @@ fn main() -> Result<(), Box<dyn Error>> {
         );
     }
 
-    languages.get_statistics(&input, &cli.ignored_directories(), &config);
+    // Files that were also in the given results are counted as they are now.
+    let mut counted = Languages::new();
+    counted.get_statistics(&input, &cli.ignored_directories(), &config);
+    languages.merge(counted);
     if config.for_each_fn.is_some() {
         process::exit(0);
     }
 
-    if let Some(format) = cli.output {
-        print!("{}", format.print(&languages).unwrap());
+    if let Some(format) = &cli.output {
+        let mut output = Output::new(&languages);
+        if let Some(depth) = cli.by_dir {
+            output = output.with_directories(&languages, depth);
+        }
+        if let Some(owners) = &owners {
+            output = output.with_owners(&languages, owners);
+        }
+        if let (Some(GroupBy::Group), Some(groups)) = (cli.group_by, &config.groups) {
+            output = output.with_groups(&languages, groups);
+        }
+        if cli.distribution {
+            output = output.with_distribution(&languages);
+        }
+        if let Some(n) = cli.top {
+            output = output.with_largest(&languages, n);
+        }
+
+        if cli.metadata {
+            let metadata = Metadata::new(&input, &cli.ignored_directories(), &config);
+            print!(
+                "{}",
+                format.print(&Envelope::new(metadata, output)).unwrap()
+            );
+        } else {
+            print!("{}", format.print(&output).unwrap());
+        }
         process::exit(0);
     }
 
@@ -85,14 +166,49 @@ fn main() -> Result<(), Box<dyn Error>> {
         columns,
         cli.files,
         io::BufWriter::new(io::stdout()),
-        cli.number_format,
+        cli.number_format.clone(),
     );
 
     if languages.iter().any(|(_, lang)| lang.inaccurate) {
         printer.print_inaccuracy_warning()?;
     }
 
-    printer.print_header()?;
+    if let Some(old) = cli.compare() {
+        let old = read_input_or_exit(old);
+
+        printer.print_header("Language")?;
+        printer.print_diff(&diff::diff(&old, &languages))?;
+        return Ok(());
+    }
+
+    let sort = cli.sort.or(config.sort);
+
+    if let Some(depth) = cli.by_dir {
+        let directories = languages.by_directory(depth);
+        let groups = directories
+            .iter()
+            .map(|(path, languages)| (path.to_string_lossy(), languages));
+
+        return print_groups(&mut printer, "Directory", groups, &languages, sort, &cli);
+    }
+
+    let grouped = match (&owners, &config.groups) {
+        (Some(owners), _) => Some(("Owner", languages.by_owner(owners))),
+        (None, Some(groups)) if cli.group_by == Some(GroupBy::Group) => {
+            Some(("Group", languages.by_group(groups)))
+        }
+        _ => None,
+    };
+
+    if let Some((header, grouped)) = grouped {
+        let groups = grouped
+            .iter()
+            .map(|(name, languages)| (Cow::from(name), languages));
+
+        return print_groups(&mut printer, header, groups, &languages, sort, &cli);
+    }
+
+    printer.print_header("Language")?;
 
     if let Some(sort_category) = cli.sort.or(config.sort) {
         for (_, ref mut language) in &mut languages {
@@ -102,11 +218,11 @@ fn main() -> Result<(), Box<dyn Error>> {
         let mut languages: Vec<_> = languages.iter().collect();
 
         match sort_category {
-            Sort::Blanks => languages.sort_by(|a, b| b.1.blanks.cmp(&a.1.blanks)),
-            Sort::Comments => languages.sort_by(|a, b| b.1.comments.cmp(&a.1.comments)),
-            Sort::Code => languages.sort_by(|a, b| b.1.code.cmp(&a.1.code)),
-            Sort::Files => languages.sort_by(|a, b| b.1.reports.len().cmp(&a.1.reports.len())),
-            Sort::Lines => languages.sort_by(|a, b| b.1.lines().cmp(&a.1.lines())),
+            Sort::Blanks => languages.sort_by_key(|(_, l)| Reverse(l.blanks)),
+            Sort::Comments => languages.sort_by_key(|(_, l)| Reverse(l.comments)),
+            Sort::Code => languages.sort_by_key(|(_, l)| Reverse(l.code)),
+            Sort::Files => languages.sort_by_key(|(_, l)| Reverse(l.files())),
+            Sort::Lines => languages.sort_by_key(|(_, l)| Reverse(l.lines())),
         }
 
         if cli.sort_reverse {
@@ -120,5 +236,102 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     printer.print_total(&languages)?;
 
+    if cli.distribution {
+        printer.print_distributions(&languages)?;
+    }
+    if let Some(n) = cli.top {
+        printer.print_largest(&languages, n)?;
+    }
+
+    Ok(())
+}
+
+/// Reads the results of a previous tokei run, or exits if they can't be
+/// parsed.
+fn read_input_or_exit(input: &str) -> LanguageMap {
+    read_input(input).unwrap_or_else(|| {
+        Cli::print_input_parse_failure(input);
+        process::exit(1);
+    })
+}
+
+/// Finds the `CODEOWNERS` file of the repository containing `paths`, or
+/// exits if there isn't one or the paths are in different repositories.
+fn find_owners_or_exit(paths: &[&str]) -> CodeOwners {
+    let mut found: Option<CodeOwners> = None;
+
+    for &path in paths {
+        let owners = match CodeOwners::find(path) {
+            Ok(Some(owners)) => owners,
+            Ok(None) => {
+                eprintln!("Error: Couldn't find a CODEOWNERS file for '{}'.", path);
+                process::exit(1);
+            }
+            Err(error) => {
+                eprintln!("Error reading CODEOWNERS:\n{}", error);
+                process::exit(1);
+            }
+        };
+
+        match &found {
+            Some(first) if first.root() != owners.root() => {
+                eprintln!(
+                    "Error: '{}' and '{}' are in different repositories, so they can't be \
+                     grouped by owner together.",
+                    paths[0], path
+                );
+                process::exit(1);
+            }
+            Some(_) => {}
+            None => found = Some(owners),
+        }
+    }
+
+    found.expect("there is always at least one path")
+}
+
+/// Prints the totals of groups of languages, sorted like the languages are,
+/// followed by the total of all languages.
+fn print_groups<'a, W, I>(
+    printer: &mut Printer<W>,
+    header: &str,
+    groups: I,
+    languages: &Languages,
+    sort: Option<Sort>,
+    cli: &Cli,
+) -> Result<(), Box<dyn Error>>
+where
+    W: io::Write,
+    I: Iterator<Item = (Cow<'a, str>, &'a Languages)>,
+{
+    let mut groups: Vec<_> = groups
+        .map(|(name, languages)| (name, languages.total(), languages))
+        .collect();
+
+    if let Some(sort_category) = sort {
+        let files = |total: &Language| total.children.values().map(Vec::len).sum::<usize>();
+
+        match sort_category {
+            Sort::Blanks => groups.sort_by_key(|(_, t, _)| Reverse(t.blanks)),
+            Sort::Comments => groups.sort_by_key(|(_, t, _)| Reverse(t.comments)),
+            Sort::Code => groups.sort_by_key(|(_, t, _)| Reverse(t.code)),
+            Sort::Files => groups.sort_by_key(|(_, t, _)| Reverse(files(t))),
+            Sort::Lines => groups.sort_by_key(|(_, t, _)| Reverse(t.lines())),
+        }
+
+        if cli.sort_reverse {
+            groups.reverse();
+        }
+    }
+
+    printer.print_header(header)?;
+    printer.print_groups(
+        groups
+            .iter()
+            .map(|(name, total, languages)| (&**name, total, *languages)),
+        cli.compact,
+    )?;
+    printer.print_total(languages)?;
+
     Ok(())
 }
@@ -0,0 +1,237 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use globset::GlobSet;
+
+use crate::utils::path::{normalise, pattern_globs};
+
+/// Where `CODEOWNERS` files are looked for in a repository, in the order
+/// GitHub and GitLab look for them.
+const LOCATIONS: &[&str] = &[
+    ".github/CODEOWNERS",
+    "CODEOWNERS",
+    ".gitlab/CODEOWNERS",
+    "docs/CODEOWNERS",
+];
+
+/// The owners of the files in a repository, read from its `CODEOWNERS` file.
+/// Both the GitHub and GitLab syntax are understood, and like both the last
+/// pattern that matches a file decides its owners. Lines that can't be parsed
+/// are skipped. GitLab sections are read for their default owners, but
+/// patterns in every section are matched as a single list.
+///
+/// ```
+/// use tokei::CodeOwners;
+///
+/// let owners = CodeOwners::new("/repo", "*.rs @rustaceans\n/docs/ @writers docs@example.com");
+///
+/// assert_eq!(owners.owners_of("src/main.rs".as_ref()), ["@rustaceans"]);
+/// assert_eq!(owners.owners_of("/repo/docs/index.md".as_ref()), ["@writers", "docs@example.com"]);
+/// assert!(owners.owners_of("README.md".as_ref()).is_empty());
+/// ```
+#[derive(Clone, Debug)]
+pub struct CodeOwners {
+    root: PathBuf,
+    rules: Vec<(GlobSet, Vec<String>)>,
+}
+
+impl CodeOwners {
+    /// The name files without owners are grouped under.
+    pub const UNOWNED: &'static str = "unowned";
+
+    /// Parses the text of a `CODEOWNERS` file whose patterns are relative to
+    /// `root`.
+    pub fn new<P: Into<PathBuf>>(root: P, text: &str) -> Self {
+        let mut rules = Vec::new();
+        let mut section_owners = Vec::new();
+
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(owners) = section_header(line) {
+                section_owners = owners;
+                continue;
+            }
+
+            let (pattern, rest) = split_pattern(line);
+            let mut owners: Vec<String> = rest
+                .split_whitespace()
+                .take_while(|owner| !owner.starts_with('#'))
+                .map(String::from)
+                .collect();
+
+            if owners.is_empty() {
+                owners = section_owners.clone();
+            }
+
+            if let Some(globs) = pattern_globs(&pattern) {
+                rules.push((globs, owners));
+            }
+        }
+
+        Self {
+            root: root.into(),
+            rules,
+        }
+    }
+
+    /// Finds and reads the `CODEOWNERS` file of the repository containing
+    /// `path`, searching `path` and then each of its parents. Returns `None`
+    /// if there isn't one.
+    pub fn find<P: AsRef<Path>>(path: P) -> io::Result<Option<Self>> {
+        let path = fs::canonicalize(path)?;
+
+        for root in path.ancestors() {
+            for location in LOCATIONS {
+                let file = root.join(location);
+
+                if file.is_file() {
+                    return Ok(Some(Self::new(root, &fs::read_to_string(file)?)));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// The directory the patterns are relative to.
+    #[must_use]
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The owners of the file at `path`, which is either absolute or relative
+    /// to the root. Returns an empty slice if the file has no owners, or is
+    /// outside of the root.
+    #[must_use]
+    pub fn owners_of(&self, path: &Path) -> &[String] {
+        let path = normalise(path);
+        let path = if path.is_absolute() {
+            match path.strip_prefix(normalise(&self.root)) {
+                Ok(path) => path,
+                Err(_) => return &[],
+            }
+        } else {
+            &path
+        };
+
+        self.rules
+            .iter()
+            .rev()
+            .find(|(globs, _)| globs.is_match(path))
+            .map_or(&[], |(_, owners)| owners)
+    }
+}
+
+/// The default owners of a GitLab section header, such as
+/// `[Docs] @writers`, `^[Optional]`, or `[Reviewed][2] @reviewers`.
+fn section_header(line: &str) -> Option<Vec<String>> {
+    let header = line.strip_prefix('^').unwrap_or(line).strip_prefix('[')?;
+    let mut rest = &header[header.find(']')? + 1..];
+
+    if rest.starts_with('[') {
+        rest = &rest[rest.find(']')? + 1..];
+    }
+
+    Some(rest.split_whitespace().map(String::from).collect())
+}
+
+/// Splits a line into its unescaped pattern and the rest of the line.
+fn split_pattern(line: &str) -> (String, &str) {
+    let mut pattern = String::new();
+    let mut chars = line.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some((_, c @ (' ' | '#'))) => pattern.push(c),
+                Some((_, c)) => {
+                    pattern.push('\\');
+                    pattern.push(c);
+                }
+                None => pattern.push('\\'),
+            },
+            c if c.is_whitespace() => return (pattern, &line[i..]),
+            c => pattern.push(c),
+        }
+    }
+
+    (pattern, "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn owners<'a>(owners: &'a CodeOwners, path: &str) -> Vec<&'a str> {
+        owners
+            .owners_of(Path::new(path))
+            .iter()
+            .map(String::as_str)
+            .collect()
+    }
+
+    #[test]
+    fn last_matching_pattern_wins() {
+        let codeowners = CodeOwners::new(
+            "/repo",
+            "# Default owners\n\
+             * @everyone\n\
+             *.js @js-owner # trailing comment\n\
+             /build/logs/ @doctocat\n\
+             docs/* docs@example.com\n\
+             apps/ @octocat\n\
+             /scripts/ @doctocat @octocat\n\
+             /scripts/generated\n\
+             path\\ with\\ spaces/ @spaces\n",
+        );
+
+        assert_eq!(owners(&codeowners, "README.md"), ["@everyone"]);
+        assert_eq!(owners(&codeowners, "src/index.js"), ["@js-owner"]);
+        assert_eq!(owners(&codeowners, "build/logs/today.log"), ["@doctocat"]);
+        assert_eq!(
+            owners(&codeowners, "src/build/logs/today.log"),
+            ["@everyone"]
+        );
+        assert_eq!(owners(&codeowners, "docs/intro.md"), ["docs@example.com"]);
+        assert_eq!(owners(&codeowners, "docs/guide/intro.md"), ["@everyone"]);
+        assert_eq!(owners(&codeowners, "web/apps/main.js"), ["@octocat"]);
+        assert_eq!(
+            owners(&codeowners, "./scripts/run.sh"),
+            ["@doctocat", "@octocat"]
+        );
+        assert!(owners(&codeowners, "scripts/generated/run.sh").is_empty());
+        assert_eq!(owners(&codeowners, "path with spaces/a.c"), ["@spaces"]);
+        assert_eq!(
+            owners(&codeowners, "/repo/lib/../src/index.js"),
+            ["@js-owner"]
+        );
+        assert!(owners(&codeowners, "/elsewhere/index.js").is_empty());
+    }
+
+    #[test]
+    fn gitlab_sections_have_default_owners() {
+        let codeowners = CodeOwners::new(
+            "/repo",
+            "[Documentation] @docs-team\n\
+             docs/\n\
+             ^[Database][2] @database-team\n\
+             model/db/\n\
+             config/db/database-setup.md @docs-team\n",
+        );
+
+        assert_eq!(owners(&codeowners, "docs/index.md"), ["@docs-team"]);
+        assert_eq!(
+            owners(&codeowners, "model/db/schema.sql"),
+            ["@database-team"]
+        );
+        assert_eq!(
+            owners(&codeowners, "config/db/database-setup.md"),
+            ["@docs-team"]
+        );
+    }
+}
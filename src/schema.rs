@@ -0,0 +1,273 @@
+//! A JSON Schema for the JSON output, derived from the types that are
+//! serialized. The tests validate real output, and output with every field
+//! set, against it, and check that `tokei.schema.json` is up to date.
+
+use schemars::generate::SchemaSettings;
+use serde_json::Value;
+
+/// The schema of the output, with or without the `--metadata` envelope.
+pub fn output_schema() -> Value {
+    SchemaSettings::draft2020_12()
+        .into_generator()
+        .into_root_schema_for::<crate::input::Input>()
+        .to_value()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+    };
+
+    use serde_json::json;
+    use tokei::{
+        CodeOwners, Config, EncodingOverrides, Groups, LanguageType, Languages, LineEnding, Report,
+        Summary,
+    };
+
+    use crate::input::{Envelope, Format, Metadata, Output};
+
+    /// Checks `value` against the parts of JSON Schema used by
+    /// `output_schema`, returning where it doesn't match.
+    fn validate(value: &Value, schema: &Value, root: &Value, path: &str) -> Result<(), String> {
+        let schema = match schema.get("$ref").and_then(Value::as_str) {
+            Some(reference) => {
+                let name = reference.trim_start_matches("#/$defs/");
+                &root["$defs"][name]
+            }
+            None => schema,
+        };
+        let fail = |message: &str| Err(format!("{}: {}", path, message));
+
+        for keyword in &["anyOf", "oneOf"] {
+            if let Some(options) = schema.get(keyword).and_then(Value::as_array) {
+                let mut errors = Vec::new();
+                for option in options {
+                    match validate(value, option, root, path) {
+                        Ok(()) => return Ok(()),
+                        Err(error) => errors.push(error),
+                    }
+                }
+                return fail(&format!("matched none of {}: {:?}", keyword, errors));
+            }
+        }
+
+        if let Some(names) = schema.get("enum").and_then(Value::as_array) {
+            if !names.contains(value) {
+                return fail(&format!("{} isn't one of the enum", value));
+            }
+        }
+
+        if let Some(constant) = schema.get("const") {
+            if value != constant {
+                return fail(&format!("{} isn't {}", value, constant));
+            }
+        }
+
+        let types = match schema.get("type") {
+            None => Vec::new(),
+            Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).collect(),
+            Some(ty) => ty.as_str().into_iter().collect(),
+        };
+        let mut matches_type = types.is_empty();
+        for ty in types {
+            matches_type |= match ty {
+                "object" => value.is_object(),
+                "array" => value.is_array(),
+                "string" => value.is_string(),
+                "boolean" => value.is_boolean(),
+                "null" => value.is_null(),
+                "integer" => value.is_u64(),
+                "number" => value.is_number(),
+                ty => return fail(&format!("unknown type {}", ty)),
+            };
+        }
+        if !matches_type {
+            return fail(&format!("{} isn't of type {}", value, schema["type"]));
+        }
+
+        if let Some(items) = value.as_array() {
+            for (i, item) in items.iter().enumerate() {
+                validate(item, &schema["items"], root, &format!("{}/{}", path, i))?;
+            }
+        }
+
+        if let Some(object) = value.as_object() {
+            for name in schema
+                .get("required")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+            {
+                if !object.contains_key(name.as_str().unwrap()) {
+                    return fail(&format!("missing required {}", name));
+                }
+            }
+
+            for (name, value) in object {
+                let path = format!("{}/{}", path, name);
+                if let Some(names) = schema.get("propertyNames") {
+                    validate(&Value::from(name.as_str()), names, root, &path)?;
+                }
+
+                match schema.get("properties").and_then(|p| p.get(name)) {
+                    Some(property) => validate(value, property, root, &path)?,
+                    None => match schema.get("additionalProperties") {
+                        Some(Value::Bool(false)) => return fail(&format!("unknown {}", name)),
+                        Some(Value::Bool(true)) | None => {}
+                        Some(additional) => validate(value, additional, root, &path)?,
+                    },
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn output_validates_against_the_schema() {
+        let data_dir = Path::new("tests").join("data");
+        let mut langs = Languages::new();
+        langs.get_statistics(&[&data_dir], &[], &Config::default());
+
+        let outputs = [
+            Format::Json.print(&Output::new(&langs)).unwrap(),
+            Format::Json
+                .print(&Output::new(&langs).with_directories(&langs, 1))
+                .unwrap(),
+            Format::Json
+                .print(
+                    &Output::new(&langs)
+                        .with_distribution(&langs)
+                        .with_largest(&langs, 3),
+                )
+                .unwrap(),
+            Format::Json
+                .print(&Envelope::new(
+                    Metadata::new(&["tests/data"], &[], &Config::default()),
+                    Output::new(&langs),
+                ))
+                .unwrap(),
+        ];
+
+        let schema = output_schema();
+        for output in &outputs {
+            let output = serde_json::from_str(output).unwrap();
+            validate(&output, &schema, &schema, "").unwrap();
+        }
+
+        let mut output: Value = serde_json::from_str(&outputs[0]).unwrap();
+        output["Total"]["unknown"] = json!(1);
+        assert!(validate(&output, &schema, &schema, "").is_err());
+    }
+
+    /// Records whether each field in `value` is set anywhere.
+    fn set_fields(value: &Value, fields: &mut BTreeMap<String, bool>) {
+        match value {
+            Value::Array(items) => items.iter().for_each(|item| set_fields(item, fields)),
+            Value::Object(object) => {
+                for (name, value) in object {
+                    *fields.entry(name.clone()).or_default() |= !value.is_null();
+                    set_fields(value, fields);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn fully_populated_output_validates_against_the_schema() {
+        let report = |name: &str| {
+            let mut report = Report::new(PathBuf::from(name));
+            report.stats.code = 1;
+            report.root = Some(String::from("."));
+            report.encoding = Some(String::from("UTF-8"));
+            report.lossy = true;
+            report.line_ending = Some(LineEnding::CrLf);
+            report
+        };
+
+        let mut main = report("src/main.rs");
+        main.stats
+            .blobs
+            .insert(LanguageType::Markdown, report("src/main.rs").stats);
+        let mut summary = Summary::default();
+        summary.add_report(&main);
+
+        let mut langs = Languages::new();
+        let rust = langs.entry(LanguageType::Rust).or_default();
+        rust.reports.push(main);
+        rust.children
+            .insert(LanguageType::Markdown, vec![report("src/main.rs")]);
+        rust.inaccurate = true;
+        rust.summary = Some(summary);
+        rust.total();
+
+        let mut config = Config::default();
+        config.hidden = Some(true);
+        config.no_ignore = Some(true);
+        config.no_ignore_parent = Some(true);
+        config.no_ignore_dot = Some(true);
+        config.no_ignore_vcs = Some(true);
+        config.treat_doc_strings_as_comments = Some(true);
+        config.types = Some(vec![LanguageType::Rust]);
+        config.encodings = Some(EncodingOverrides::new(vec![("legacy/*.c", "Shift_JIS")]).unwrap());
+        config.custom_blocks = Some(
+            vec![(String::from("docs"), LanguageType::Markdown)]
+                .into_iter()
+                .collect(),
+        );
+        config.template_tags = Some(
+            vec![(String::from("md"), LanguageType::Markdown)]
+                .into_iter()
+                .collect(),
+        );
+        config.path_prefixes = Some(
+            vec![(String::from("/build"), String::new())]
+                .into_iter()
+                .collect(),
+        );
+        config.summary_only = Some(true);
+        let owners = CodeOwners::new(".", "*.rs @rust\n");
+        let groups = Groups::new(vec![("rust", vec!["*.rs"])]).unwrap();
+        let output = Output::new(&langs)
+            .with_directories(&langs, 1)
+            .with_owners(&langs, &owners)
+            .with_groups(&langs, &groups)
+            .with_distribution(&langs)
+            .with_largest(&langs, 3);
+        let envelope = Envelope::new(Metadata::new(&["tests/data"], &[], &config), output);
+
+        let envelope: Value =
+            serde_json::from_str(&Format::Json.print(&envelope).unwrap()).unwrap();
+
+        // Every optional field is set somewhere, so that a field missing
+        // from the schema is caught as unknown. The commit is only set in a
+        // git checkout.
+        let mut fields = BTreeMap::new();
+        set_fields(&envelope, &mut fields);
+        let unset: Vec<_> = fields
+            .into_iter()
+            .filter(|(name, set)| !set && name != "git_commit")
+            .collect();
+        assert!(unset.is_empty(), "fields aren't set: {:?}", unset);
+
+        let schema = output_schema();
+        validate(&envelope, &schema, &schema, "").unwrap();
+        validate(&envelope["results"], &schema, &schema, "").unwrap();
+    }
+
+    #[test]
+    fn published_schema_is_up_to_date() {
+        let published = std::fs::read_to_string("tokei.schema.json").unwrap();
+        let published: Value = serde_json::from_str(&published).unwrap();
+
+        assert!(
+            published == output_schema(),
+            "tokei.schema.json is out of date, update it with `tokei --print-schema`"
+        );
+    }
+}
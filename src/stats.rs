@@ -3,7 +3,10 @@ use std::{collections::BTreeMap, fmt, ops, path::PathBuf};
 use crate::LanguageType;
 
 /// A struct representing stats about a single blob of code.
-#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[derive(
+    Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize, schemars::JsonSchema,
+)]
+#[schemars(deny_unknown_fields)]
 #[non_exhaustive]
 pub struct CodeStats {
     /// The blank lines in the blob.
@@ -66,13 +69,50 @@ impl ops::AddAssign<&'_ CodeStats> for CodeStats {
 }
 
 /// A struct representing the statistics of a file.
-#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, schemars::JsonSchema)]
+#[schemars(deny_unknown_fields)]
 #[non_exhaustive]
 pub struct Report {
     /// The code statistics found in the file.
     pub stats: CodeStats,
-    /// File name.
+    /// File name. Files that were counted from disk are named by their path
+    /// relative to [`Report::root`], with `/` separators.
     pub name: PathBuf,
+    /// The path the file was counted from, such as `.` or `src`, with `/`
+    /// separators. `None` if the report wasn't counted from disk.
+    #[serde(default)]
+    pub root: Option<String>,
+    /// The name of the encoding the file was decoded from, if it was read
+    /// from disk.
+    #[serde(default)]
+    pub encoding: Option<String>,
+    /// Whether some of the file couldn't be decoded, which can make its
+    /// statistics inaccurate.
+    #[serde(default)]
+    pub lossy: bool,
+    /// The line terminators used in the file, if it was read from disk and
+    /// had any.
+    #[serde(default)]
+    pub line_ending: Option<LineEnding>,
+    /// The path the file was counted from, if [`Config::path_prefixes`]
+    /// rewrote its name, so it can still be found on disk.
+    ///
+    /// [`Config::path_prefixes`]: crate::Config::path_prefixes
+    #[serde(skip)]
+    pub(crate) counted_from: Option<PathBuf>,
+}
+
+/// The style of line terminators used in a file.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize, schemars::JsonSchema)]
+pub enum LineEnding {
+    /// Unix style `\n`.
+    Lf,
+    /// Windows style `\r\n`.
+    CrLf,
+    /// Classic Mac OS style `\r`.
+    Cr,
+    /// More than one style in the same file.
+    Mixed,
 }
 
 impl Report {
@@ -86,6 +126,37 @@ impl Report {
             ..Self::default()
         }
     }
+
+    /// The path of the file including its root, which is how files are told
+    /// apart when results are merged or compared.
+    ///
+    /// ```
+    /// # use tokei::*;
+    /// let mut report = Report::new("lib.rs".into());
+    /// assert_eq!(report.path(), std::path::Path::new("lib.rs"));
+    ///
+    /// report.root = Some(String::from("src"));
+    /// assert_eq!(report.path(), std::path::Path::new("src/lib.rs"));
+    /// ```
+    #[must_use]
+    pub fn path(&self) -> PathBuf {
+        match self.root.as_deref() {
+            None | Some(".") => self.name.clone(),
+            Some(root) => PathBuf::from(format!(
+                "{}/{}",
+                root.trim_end_matches('/'),
+                self.name.display()
+            )),
+        }
+    }
+}
+
+impl Report {
+    /// The path the file was counted from, before any prefixes were
+    /// rewritten, for finding where the file is on disk.
+    pub(crate) fn counted_path(&self) -> PathBuf {
+        self.counted_from.clone().unwrap_or_else(|| self.path())
+    }
 }
 
 impl ops::AddAssign<CodeStats> for Report {
@@ -94,6 +165,89 @@ impl ops::AddAssign<CodeStats> for Report {
     }
 }
 
+/// The combined statistics of files that were counted without keeping a
+/// [`Report`] for each of them, see [`Config::summary_only`].
+///
+/// [`Config::summary_only`]: struct.Config.html#structfield.summary_only
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, schemars::JsonSchema)]
+#[schemars(deny_unknown_fields)]
+#[non_exhaustive]
+pub struct Summary {
+    /// The number of files.
+    pub files: usize,
+    /// The statistics of the files added together, with the languages
+    /// embedded in them as blobs.
+    pub stats: CodeStats,
+    /// The number of files each embedded language was found in.
+    pub children: BTreeMap<LanguageType, usize>,
+}
+
+impl Summary {
+    /// Adds the statistics of `report` to the summary.
+    pub fn add_report(&mut self, report: &Report) {
+        self.files += 1;
+        for language in report.stats.blobs.keys() {
+            *self.children.entry(*language).or_default() += 1;
+        }
+        self.stats += &report.stats;
+    }
+}
+
+impl ops::AddAssign for Summary {
+    fn add_assign(&mut self, rhs: Self) {
+        self.files += rhs.files;
+        for (language, files) in rhs.children {
+            *self.children.entry(language).or_default() += files;
+        }
+        self.stats += rhs.stats;
+    }
+}
+
+/// How the number of lines is distributed across files. Percentiles are
+/// taken by the nearest rank, so they're always the lines of one of the files.
+///
+/// ```
+/// # use tokei::*;
+/// let distribution = Distribution::new(vec![10, 40, 20, 30]).unwrap();
+///
+/// assert_eq!(distribution.mean, 25.0);
+/// assert_eq!(distribution.median, 20);
+/// assert_eq!(distribution.p90, 40);
+/// assert_eq!(distribution.max, 40);
+/// ```
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, schemars::JsonSchema)]
+#[schemars(deny_unknown_fields)]
+#[non_exhaustive]
+pub struct Distribution {
+    /// The mean lines per file.
+    pub mean: f64,
+    /// The median lines per file.
+    pub median: usize,
+    /// The lines per file that 90% of files are at or below.
+    pub p90: usize,
+    /// The lines of the largest file.
+    pub max: usize,
+}
+
+impl Distribution {
+    /// The distribution of `lines`, the lines in each file. Returns `None` if
+    /// there aren't any files.
+    pub fn new<I: IntoIterator<Item = usize>>(lines: I) -> Option<Self> {
+        let mut lines: Vec<_> = lines.into_iter().collect();
+        lines.sort_unstable();
+
+        let max = *lines.last()?;
+        let percentile = |percent: usize| lines[(lines.len() * percent).div_ceil(100).max(1) - 1];
+
+        Some(Self {
+            mean: lines.iter().sum::<usize>() as f64 / lines.len() as f64,
+            median: percentile(50),
+            p90: percentile(90),
+            max,
+        })
+    }
+}
+
 #[doc(hidden)]
 #[must_use]
 pub fn find_char_boundary(s: &str, index: usize) -> usize {
@@ -122,7 +276,8 @@ macro_rules! display_stats {
 
 impl fmt::Display for Report {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let name = self.name.to_string_lossy();
+        let path = self.path();
+        let name = path.to_string_lossy();
         let name_length = name.len();
 
         let max_len = f.width().unwrap_or(25);
@@ -0,0 +1,233 @@
+use std::{
+    fs::File,
+    io::{self, Chain, Cursor, Read},
+    path::Path,
+};
+
+use chardetng::EncodingDetector;
+use encoding_rs::{Decoder, Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+use crate::config::Config;
+
+/// How many bytes from the start of a file are used to detect its encoding.
+const SNIFF_LEN: usize = 64 * 1024;
+/// How many bytes are decoded at a time.
+const BUFFER_LEN: usize = 8 * 1024;
+
+/// Opens the file at `path` for reading as UTF-8. The file is decoded with
+/// the encoding `config` sets for its path, or else the encoding detected
+/// from the start of the file.
+pub(crate) fn open(
+    path: &Path,
+    config: &Config,
+) -> io::Result<DecodeReader<Chain<Cursor<Vec<u8>>, File>>> {
+    let mut file = File::open(path)?;
+    let mut prefix = Vec::new();
+    (&mut file)
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut prefix)?;
+
+    let encoding = config
+        .encodings
+        .as_ref()
+        .and_then(|overrides| overrides.encoding_for(path))
+        .unwrap_or_else(|| detect(&prefix, prefix.len() < SNIFF_LEN));
+
+    Ok(DecodeReader::new(Cursor::new(prefix).chain(file), encoding))
+}
+
+/// Reads the whole file at `path` as UTF-8, decoded the same way it is when
+/// it's counted with `config`.
+///
+/// ```no_run
+/// use tokei::{read_file, Config};
+///
+/// let text = read_file("src/main.rs", &Config::default()).unwrap();
+/// ```
+pub fn read_file<P: AsRef<Path>>(path: P, config: &Config) -> io::Result<Vec<u8>> {
+    let mut text = Vec::new();
+    open(path.as_ref(), config)?.read_to_end(&mut text)?;
+
+    Ok(text)
+}
+
+/// Detects the encoding of text beginning with `prefix`, where `eof` is
+/// whether `prefix` is the whole text. A byte order mark always decides the
+/// encoding, otherwise text that is valid UTF-8 is taken to be UTF-8.
+pub(crate) fn detect(prefix: &[u8], eof: bool) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(prefix) {
+        return encoding;
+    }
+
+    if let Some(encoding) = detect_utf16(prefix) {
+        return encoding;
+    }
+
+    match std::str::from_utf8(prefix) {
+        Ok(_) => return UTF_8,
+        // The prefix can end part way through a character.
+        Err(error) if error.error_len().is_none() && !eof => return UTF_8,
+        Err(_) => {}
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(prefix, eof);
+    detector.guess(None, true)
+}
+
+/// UTF-16 without a byte order mark is recognised by most of the text being
+/// ASCII, which leaves every other byte zero.
+fn detect_utf16(prefix: &[u8]) -> Option<&'static Encoding> {
+    let pairs = prefix.len() / 2;
+    let (mut even, mut odd) = (0, 0);
+
+    for pair in prefix.chunks_exact(2) {
+        even += usize::from(pair[0] == 0);
+        odd += usize::from(pair[1] == 0);
+    }
+
+    if pairs == 0 {
+        None
+    } else if odd > pairs / 2 && even <= pairs / 8 {
+        Some(UTF_16LE)
+    } else if even > pairs / 2 && odd <= pairs / 8 {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// Decodes the text read from `inner` to UTF-8, replacing anything that
+/// can't be decoded with U+FFFD and keeping track of whether it had to.
+pub(crate) struct DecodeReader<R> {
+    inner: R,
+    decoder: Decoder,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    position: usize,
+    finished: bool,
+    lossy: bool,
+}
+
+impl<R: Read> DecodeReader<R> {
+    pub(crate) fn new(inner: R, encoding: &'static Encoding) -> Self {
+        Self {
+            inner,
+            decoder: encoding.new_decoder_with_bom_removal(),
+            input: vec![0; BUFFER_LEN],
+            output: Vec::new(),
+            position: 0,
+            finished: false,
+            lossy: false,
+        }
+    }
+
+    /// The encoding the text is being decoded from.
+    pub(crate) fn encoding(&self) -> &'static Encoding {
+        self.decoder.encoding()
+    }
+
+    /// Whether any of the text read so far couldn't be decoded.
+    pub(crate) fn is_lossy(&self) -> bool {
+        self.lossy
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        let read = loop {
+            match self.inner.read(&mut self.input) {
+                Ok(read) => break read,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            }
+        };
+        let last = read == 0;
+
+        // Sized so that the decoder always consumes all of the input.
+        let capacity = self
+            .decoder
+            .max_utf8_buffer_length(read)
+            .expect("decode buffer length overflowed");
+        self.output.resize(capacity, 0);
+
+        let (_, _, written, replaced) =
+            self.decoder
+                .decode_to_utf8(&self.input[..read], &mut self.output, last);
+
+        self.output.truncate(written);
+        self.position = 0;
+        self.finished = last;
+        self.lossy |= replaced;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecodeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.output.len() {
+            if self.finished {
+                return Ok(0);
+            }
+
+            self.fill()?;
+        }
+
+        let len = buf.len().min(self.output.len() - self.position);
+        buf[..len].copy_from_slice(&self.output[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use encoding_rs::{SHIFT_JIS, WINDOWS_1252};
+
+    fn decode(text: &[u8], encoding: &'static Encoding) -> (String, bool) {
+        let mut reader = DecodeReader::new(text, encoding);
+        let mut decoded = String::new();
+        reader.read_to_string(&mut decoded).unwrap();
+
+        (decoded, reader.is_lossy())
+    }
+
+    #[test]
+    fn detects_utf16_without_bom() {
+        let text = "fn main() {}\n".encode_utf16();
+        let le: Vec<u8> = text.clone().flat_map(u16::to_le_bytes).collect();
+        let be: Vec<u8> = text.flat_map(u16::to_be_bytes).collect();
+
+        assert_eq!(detect(&le, true), UTF_16LE);
+        assert_eq!(detect(&be, true), UTF_16BE);
+    }
+
+    #[test]
+    fn detects_legacy_encodings() {
+        let (latin1, _, _) = WINDOWS_1252.encode("// Café, naïve, façade\nint x;\n");
+        let (sjis, _, _) =
+            SHIFT_JIS.encode("// こんにちは、世界。日本語のコメントです。\nint x;\n");
+
+        assert_eq!(detect(b"fn main() {}\n", true), UTF_8);
+        assert_eq!(detect(&latin1, true), WINDOWS_1252);
+        assert_eq!(detect(&sjis, true), SHIFT_JIS);
+    }
+
+    #[test]
+    fn bom_decides_encoding() {
+        assert_eq!(detect(b"\xFF\xFEa\0", true), UTF_16LE);
+        assert_eq!(decode(b"\xEF\xBB\xBFfn", UTF_8), ("fn".to_owned(), false));
+    }
+
+    #[test]
+    fn invalid_text_is_lossy() {
+        assert_eq!(
+            decode(b"caf\xE9\n", UTF_8),
+            ("caf\u{FFFD}\n".to_owned(), true)
+        );
+        assert_eq!(
+            decode(b"caf\xE9\n", WINDOWS_1252),
+            ("café\n".to_owned(), false)
+        );
+    }
+}
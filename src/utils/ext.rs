@@ -2,6 +2,7 @@
 
 pub(crate) trait AsciiExt {
     fn is_whitespace(&self) -> bool;
+    #[allow(dead_code)]
     fn is_not_line_ending_whitespace(&self) -> bool;
     fn is_line_ending_whitespace(&self) -> bool;
 }
@@ -21,6 +22,7 @@ impl AsciiExt for u8 {
 }
 
 pub(crate) trait SliceExt {
+    #[allow(dead_code)]
     fn trim_first_and_last_line_of_whitespace(&self) -> &Self;
     fn trim_start(&self) -> &Self;
     fn trim(&self) -> &Self;
@@ -159,5 +161,10 @@ mod tests {
         fn trim_first_and_last_line_of_whitespace_doesnt_panic(input: Vec<u8>) {
             let _ = &input.trim_first_and_last_line_of_whitespace();
         }
+
+        #[test]
+        fn trim_doesnt_panic(input: Vec<u8>) {
+            let _ = &input.trim();
+        }
     }
 }
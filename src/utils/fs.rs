@@ -1,4 +1,7 @@
-use std::{collections::BTreeMap, path::Path};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 
 use ignore::{overrides::OverrideBuilder, DirEntry, WalkBuilder, WalkState::Continue};
 
@@ -7,9 +10,15 @@ use rayon::prelude::*;
 use crate::{
     config::Config,
     language::{Language, LanguageType},
+    stats::Report,
+    utils::path::{portable, replace_prefix},
 };
 
 const IGNORE_FILE: &str = ".tokeignore";
+/// How many files can be found ahead of the files being counted. The walk
+/// waits for counting to catch up, so memory use doesn't grow with the number
+/// of files.
+const PENDING_FILES: usize = 1024;
 
 pub fn get_all_files<A: AsRef<Path>>(
     paths: &[A],
@@ -18,7 +27,9 @@ pub fn get_all_files<A: AsRef<Path>>(
     config: &Config,
 ) {
     let languages = parking_lot::Mutex::new(languages);
-    let (tx, rx) = crossbeam_channel::unbounded();
+    let (tx, rx) = crossbeam_channel::bounded(PENDING_FILES);
+    let roots = Roots::new(paths, config);
+    let summary_only = config.summary_only.unwrap_or(false);
 
     let mut paths = paths.iter();
     let mut walker = WalkBuilder::new(paths.next().unwrap());
@@ -55,33 +66,36 @@ pub fn get_all_files<A: AsRef<Path>>(
         .ignore(ignore_dot)
         .parents(ignore && config.no_ignore_parent.map(|b| !b).unwrap_or(true));
 
-    walker.build_parallel().run(move || {
-        let tx = tx.clone();
-        Box::new(move |entry| {
-            let entry = match entry {
-                Ok(entry) => entry,
-                Err(error) => {
-                    use ignore::Error;
-                    if let Error::WithDepth { err: ref error, .. } = error {
-                        if let Error::WithPath {
-                            ref path,
-                            err: ref error,
-                        } = **error
-                        {
-                            error!("{} reading {}", error, path.display());
-                            return Continue;
+    let walker = walker.build_parallel();
+    let walk = std::thread::spawn(move || {
+        walker.run(move || {
+            let tx = tx.clone();
+            Box::new(move |entry| {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(error) => {
+                        use ignore::Error;
+                        if let Error::WithDepth { err: ref error, .. } = error {
+                            if let Error::WithPath {
+                                ref path,
+                                err: ref error,
+                            } = **error
+                            {
+                                error!("{} reading {}", error, path.display());
+                                return Continue;
+                            }
                         }
+                        error!("{}", error);
+                        return Continue;
                     }
-                    error!("{}", error);
-                    return Continue;
-                }
-            };
+                };
 
-            if entry.file_type().map_or(false, |ft| ft.is_file()) {
-                tx.send(entry).unwrap();
-            }
+                if entry.file_type().is_some_and(|ft| ft.is_file()) {
+                    tx.send(entry).unwrap();
+                }
 
-            Continue
+                Continue
+            })
         })
     });
 
@@ -92,15 +106,26 @@ pub fn get_all_files<A: AsRef<Path>>(
 
     let process = |(entry, language): (DirEntry, LanguageType)| {
         let result = language.parse(entry.into_path(), config);
+        // Templates such as PHP files are counted as the language they're
+        // hosted in, with the server side code as a child language.
+        let language = language.host().unwrap_or(language);
         let mut lock = languages.lock();
         let entry = lock.entry(language).or_insert_with(Language::new);
         match result {
-            Ok(stats) => {
+            Ok(mut stats) => {
+                roots.relativise(&mut stats);
+                if stats.lossy {
+                    entry.mark_inaccurate();
+                }
                 let func = config.for_each_fn;
                 if let Some(f) = func {
                     f(language, stats.clone())
                 };
-                entry.add_report(stats)
+                if summary_only {
+                    entry.add_to_summary(&stats)
+                } else {
+                    entry.add_report(stats)
+                }
             }
             Err((error, path)) => {
                 entry.mark_inaccurate();
@@ -110,10 +135,69 @@ pub fn get_all_files<A: AsRef<Path>>(
     };
 
     if let Some(types) = config.types.as_deref() {
-        rx_iter.filter(|(_, l)| types.contains(l)).for_each(process)
+        rx_iter
+            .filter(|(_, l)| types.contains(l) || l.host().is_some_and(|h| types.contains(&h)))
+            .for_each(process)
     } else {
         rx_iter.for_each(process)
     }
+
+    walk.join().expect("Walking the paths panicked");
+}
+
+/// The paths files are counted from, which reports are named relative to.
+struct Roots {
+    /// Each root's directory, as given, and its label.
+    roots: Vec<(PathBuf, String)>,
+    prefixes: BTreeMap<String, String>,
+}
+
+impl Roots {
+    fn new<A: AsRef<Path>>(paths: &[A], config: &Config) -> Self {
+        let prefixes = config.path_prefixes.clone().unwrap_or_default();
+        let roots = paths
+            .iter()
+            .map(|path| {
+                let path = path.as_ref();
+                // Files that were counted directly are named relative to
+                // the directory they're in.
+                let directory = if path.is_file() {
+                    path.parent().unwrap_or_else(|| Path::new(""))
+                } else {
+                    path
+                };
+
+                let label = replace_prefix(&portable(directory), &prefixes);
+                (directory.to_owned(), label)
+            })
+            .collect();
+
+        Self { roots, prefixes }
+    }
+
+    /// Names `report` by its path relative to the deepest root containing
+    /// it, and records that root.
+    fn relativise(&self, report: &mut Report) {
+        if !self.prefixes.is_empty() {
+            report.counted_from = Some(report.name.clone());
+        }
+
+        let root = self
+            .roots
+            .iter()
+            .filter(|(directory, _)| report.name.starts_with(directory))
+            .max_by_key(|(directory, _)| directory.components().count());
+
+        let name = match root {
+            Some((directory, label)) => {
+                report.root = Some(label.clone());
+                report.name.strip_prefix(directory).unwrap_or(&report.name)
+            }
+            None => &report.name,
+        };
+
+        report.name = replace_prefix(&portable(name), &self.prefixes).into();
+    }
 }
 
 pub(crate) fn get_extension(path: &Path) -> Option<String> {
@@ -126,17 +210,18 @@ pub(crate) fn get_filename(path: &Path) -> Option<String> {
 
 #[cfg(test)]
 mod tests {
-    use std::fs;
+    use std::{fs, path::Path};
 
     use tempfile::TempDir;
 
     use super::IGNORE_FILE;
     use crate::{
-        config::Config,
+        config::{Config, EncodingOverrides},
         language::{languages::Languages, LanguageType},
+        CodeOwners, Groups,
     };
 
-    const FILE_CONTENTS: &[u8] = &*b"fn main() {}";
+    const FILE_CONTENTS: &[u8] = b"fn main() {}";
     const FILE_NAME: &str = "main.rs";
     const IGNORE_PATTERN: &str = "*.rs";
     const LANGUAGE: &LanguageType = &LanguageType::Rust;
@@ -483,4 +568,124 @@ mod tests {
 
         assert!(languages.get(LANGUAGE).is_some());
     }
+
+    #[test]
+    fn encoding_overrides_and_lossy_files() {
+        let dir = TempDir::new().expect("Couldn't create temp dir.");
+        let mut languages = Languages::new();
+        let legacy = dir.path().join("legacy");
+
+        fs::create_dir(&legacy).unwrap();
+        fs::write(legacy.join(FILE_NAME), b"// caf\xE9\nfn main() {}\n").unwrap();
+        fs::write(dir.path().join(FILE_NAME), b"// caf\xE9\nfn main() {}\n").unwrap();
+
+        let config = Config {
+            encodings: Some(EncodingOverrides::new(vec![("legacy/*.rs", "utf-8")]).unwrap()),
+            ..Config::default()
+        };
+
+        super::get_all_files(
+            &[dir.path().to_str().unwrap()],
+            &[],
+            &mut languages,
+            &config,
+        );
+
+        let rust = languages.get(LANGUAGE).unwrap();
+        let encoding_of = |path: &Path| {
+            rust.reports
+                .iter()
+                .find(|report| report.path() == path)
+                .map(|report| (report.encoding.as_deref(), report.lossy))
+                .unwrap()
+        };
+
+        assert_eq!(encoding_of(&legacy.join(FILE_NAME)), (Some("UTF-8"), true));
+        assert_eq!(
+            encoding_of(&dir.path().join(FILE_NAME)),
+            (Some("windows-1252"), false)
+        );
+        assert!(rust.inaccurate);
+    }
+
+    #[test]
+    fn reports_are_named_relative_to_their_root() {
+        let dir = TempDir::new().expect("Couldn't create temp dir.");
+        let src = dir.path().join("src");
+        fs::create_dir(&src).unwrap();
+        fs::write(src.join(FILE_NAME), FILE_CONTENTS).unwrap();
+        fs::write(dir.path().join("build.rs"), FILE_CONTENTS).unwrap();
+
+        let root = dir.path().to_str().unwrap();
+        let config = Config {
+            path_prefixes: Some(
+                vec![(root.to_owned(), String::from("repo"))]
+                    .into_iter()
+                    .collect(),
+            ),
+            ..Config::default()
+        };
+        let mut languages = Languages::new();
+
+        super::get_all_files(
+            &[root, src.join("..").join("build.rs").to_str().unwrap()],
+            &[],
+            &mut languages,
+            &config,
+        );
+
+        let mut names: Vec<_> = languages[LANGUAGE]
+            .reports
+            .iter()
+            .map(|report| {
+                (
+                    report.root.as_deref().unwrap(),
+                    report.name.to_str().unwrap(),
+                )
+            })
+            .collect();
+        names.sort_unstable();
+
+        assert_eq!(
+            names,
+            [
+                ("repo", "build.rs"),
+                ("repo", "build.rs"),
+                ("repo", "src/main.rs")
+            ]
+        );
+    }
+
+    #[test]
+    fn owners_and_groups_match_files_whose_prefixes_were_rewritten() {
+        let dir = TempDir::new().expect("Couldn't create temp dir.");
+        let src = dir.path().join("src");
+        fs::create_dir(&src).unwrap();
+        fs::write(src.join(FILE_NAME), FILE_CONTENTS).unwrap();
+
+        let root = dir.path().to_str().unwrap();
+        let config = Config {
+            path_prefixes: Some(
+                vec![(root.to_owned(), String::from("repo"))]
+                    .into_iter()
+                    .collect(),
+            ),
+            ..Config::default()
+        };
+        let mut languages = Languages::new();
+        languages.get_statistics(&[root], &[], &config);
+
+        let report = &languages[LANGUAGE].reports[0];
+        assert_eq!(report.root.as_deref(), Some("repo"));
+        assert_eq!(report.name, Path::new("src").join(FILE_NAME));
+
+        let owners = CodeOwners::new(dir.path(), "/src/ @backend\n");
+        let by_owner = languages.by_owner(&owners);
+        assert_eq!(by_owner.keys().collect::<Vec<_>>(), ["@backend"]);
+
+        let name = dir.path().file_name().unwrap().to_str().unwrap();
+        let groups = Groups::new(vec![("checkout", vec![format!("{}/", name)])]).unwrap();
+        let by_group = languages.by_group(&groups);
+        assert_eq!(by_group.keys().collect::<Vec<_>>(), ["checkout"]);
+    }
 }
@@ -0,0 +1,114 @@
+use std::borrow::Cow;
+
+use memchr::{memchr, memchr2};
+
+use crate::stats::LineEnding;
+
+/// Tracks which line terminators have been seen in a file, while rewriting
+/// each lone `\r` to `\n` so that lines only ever need splitting on `\n`.
+/// Rewriting rather than removing keeps the text the same length, so
+/// positions in it still refer to the original.
+#[derive(Debug, Default)]
+pub(crate) struct LineEndings {
+    lf: bool,
+    crlf: bool,
+    cr: bool,
+}
+
+impl LineEndings {
+    /// Normalises the line terminators in `text`, which follows any text
+    /// normalised before it. Unless `eof` is set, a `\r` at the end of `text`
+    /// may be followed by a `\n` that hasn't been read yet, so it's left
+    /// alone. Returns how many bytes of `text` were normalised.
+    pub(crate) fn normalise(&mut self, text: &mut [u8], eof: bool) -> usize {
+        let mut position = 0;
+
+        while let Some(i) = memchr2(b'\r', b'\n', &text[position..]) {
+            let i = position + i;
+            position = i + 1;
+
+            if text[i] == b'\n' {
+                if i > 0 && text[i - 1] == b'\r' {
+                    self.crlf = true;
+                } else {
+                    self.lf = true;
+                }
+            } else if i + 1 == text.len() && !eof {
+                return i;
+            } else if text.get(i + 1) != Some(&b'\n') {
+                text[i] = b'\n';
+                self.cr = true;
+            }
+        }
+
+        text.len()
+    }
+
+    /// The style of line terminators seen so far, if there were any.
+    pub(crate) fn style(&self) -> Option<LineEnding> {
+        match (self.lf, self.crlf, self.cr) {
+            (false, false, false) => None,
+            (true, false, false) => Some(LineEnding::Lf),
+            (false, true, false) => Some(LineEnding::CrLf),
+            (false, false, true) => Some(LineEnding::Cr),
+            _ => Some(LineEnding::Mixed),
+        }
+    }
+}
+
+/// `text` with each lone `\r` rewritten to `\n`, only copying it if it has
+/// any.
+pub(crate) fn normalise(text: &[u8]) -> Cow<'_, [u8]> {
+    let mut position = 0;
+
+    while let Some(i) = memchr(b'\r', &text[position..]) {
+        let i = position + i;
+        position = i + 1;
+
+        if text.get(i + 1) != Some(&b'\n') {
+            let mut text = text.to_vec();
+            LineEndings::default().normalise(&mut text[i..], true);
+            return Cow::Owned(text);
+        }
+    }
+
+    Cow::Borrowed(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style(text: &[u8]) -> Option<LineEnding> {
+        let mut endings = LineEndings::default();
+        endings.normalise(&mut text.to_vec(), true);
+        endings.style()
+    }
+
+    #[test]
+    fn detects_styles() {
+        assert_eq!(style(b"a"), None);
+        assert_eq!(style(b"a\nb\n"), Some(LineEnding::Lf));
+        assert_eq!(style(b"a\r\nb\r\n"), Some(LineEnding::CrLf));
+        assert_eq!(style(b"a\rb\r"), Some(LineEnding::Cr));
+        assert_eq!(style(b"a\r\nb\rc\n"), Some(LineEnding::Mixed));
+    }
+
+    #[test]
+    fn only_lone_carriage_returns_are_rewritten() {
+        assert_eq!(&*normalise(b"a\rb\r\nc\r"), b"a\nb\r\nc\n");
+        assert!(matches!(normalise(b"a\r\nb\n"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn carriage_return_at_end_waits_for_more_text() {
+        let mut endings = LineEndings::default();
+        let mut text = b"a\r".to_vec();
+
+        assert_eq!(endings.normalise(&mut text, false), 1);
+
+        text.push(b'\n');
+        assert_eq!(endings.normalise(&mut text[1..], false), 2);
+        assert_eq!(endings.style(), Some(LineEnding::CrLf));
+    }
+}
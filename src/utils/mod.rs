@@ -1,4 +1,7 @@
 #[macro_use]
 mod macros;
+pub(crate) mod encoding;
 pub(crate) mod ext;
 pub mod fs;
+pub(crate) mod line_ending;
+pub(crate) mod path;
@@ -0,0 +1,111 @@
+use std::{
+    collections::BTreeMap,
+    path::{Component, Path, PathBuf},
+};
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+/// Builds the globs matching the files a `.gitignore` style pattern covers.
+/// A pattern containing a `/` other than at its end is relative to the root,
+/// otherwise it matches at any depth, and a pattern matching a directory
+/// covers everything in it. A pattern ending in `/*` only covers the files
+/// directly in the directory, as in `CODEOWNERS` files. Returns `None` if the
+/// pattern is empty or invalid.
+pub(crate) fn pattern_globs(pattern: &str) -> Option<GlobSet> {
+    let anchored = pattern.trim_end_matches('/').contains('/');
+    let directory = pattern.ends_with('/');
+    let pattern = pattern.trim_matches('/');
+
+    if pattern.is_empty() {
+        return None;
+    }
+
+    let base = if anchored || pattern.starts_with("**") {
+        pattern.to_owned()
+    } else {
+        format!("**/{}", pattern)
+    };
+
+    let mut patterns = Vec::new();
+    if !directory {
+        patterns.push(base.clone());
+    }
+    if !pattern.ends_with("/*") {
+        patterns.push(format!("{}/**", base));
+    }
+
+    let mut globs = GlobSetBuilder::new();
+    for pattern in patterns {
+        globs.add(
+            GlobBuilder::new(&pattern)
+                .literal_separator(true)
+                .build()
+                .ok()?,
+        );
+    }
+
+    globs.build().ok()
+}
+
+/// Removes `.` components and resolves `..` components without touching the
+/// file system.
+pub(crate) fn normalise(path: &Path) -> PathBuf {
+    let mut normalised = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if normalised.file_name().is_some() => {
+                normalised.pop();
+            }
+            component => normalised.push(component),
+        }
+    }
+
+    normalised
+}
+
+/// Normalises `path` and writes it with `/` separators, so the same path is
+/// written the same way on every platform. An empty path is written as `.`.
+pub(crate) fn portable(path: &Path) -> String {
+    let mut portable = String::new();
+
+    for component in normalise(path).components() {
+        match component {
+            Component::Prefix(prefix) => portable.push_str(&prefix.as_os_str().to_string_lossy()),
+            Component::RootDir => portable.push('/'),
+            component => {
+                if !portable.is_empty() && !portable.ends_with('/') {
+                    portable.push('/');
+                }
+                portable.push_str(&component.as_os_str().to_string_lossy());
+            }
+        }
+    }
+
+    if portable.is_empty() {
+        portable.push('.');
+    }
+
+    portable
+}
+
+/// Replaces the longest prefix of the portable `path` found in `prefixes`
+/// with what it maps to. Prefixes match whole components, and a path that is
+/// stripped entirely becomes `.`.
+pub(crate) fn replace_prefix(path: &str, prefixes: &BTreeMap<String, String>) -> String {
+    let replacement = prefixes
+        .iter()
+        .filter_map(|(prefix, replacement)| {
+            let prefix = portable(Path::new(prefix));
+            let rest = Path::new(path).strip_prefix(&prefix).ok()?;
+
+            Some((prefix.len(), Path::new(replacement).join(rest)))
+        })
+        .max_by_key(|(length, _)| *length);
+
+    match replacement {
+        Some((_, path)) => portable(&path),
+        None => path.to_owned(),
+    }
+}
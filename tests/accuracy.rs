@@ -62,10 +62,8 @@ mod config {
     #[test]
     fn treating_comments_as_code() {
         let mut languages = Languages::new();
-        let config = Config {
-            treat_doc_strings_as_comments: Some(true),
-            ..Config::default()
-        };
+        let mut config = Config::default();
+        config.treat_doc_strings_as_comments = Some(true);
 
         languages.get_statistics(&["tests/data/python.py"], &[], &config);
 
@@ -83,6 +81,22 @@ mod config {
         assert_eq!(language.comments, 7);
         assert_eq!(language.code, 5);
     }
+
+    #[test]
+    fn restructuredtext_children() {
+        let mut languages = Languages::new();
+        languages.get_statistics(
+            &["tests/data/restructuredtext.rst"],
+            &[],
+            &Config::default(),
+        );
+
+        let rst = &languages[&LanguageType::ReStructuredText];
+        let python = &rst.children[&LanguageType::Python][0].stats;
+
+        assert_eq!((rst.code, rst.comments, rst.blanks), (5, 1, 4));
+        assert_eq!((python.code, python.comments, python.blanks), (2, 1, 1));
+    }
 }
 
 include!(concat!(env!("OUT_DIR"), "/tests.rs"));
@@ -0,0 +1,16 @@
+// 16 lines 6 code 7 comments 3 blanks
+namespace Ns
+{
+    /// <summary>
+    /// Adds two numbers.
+    /// </summary>
+    ///
+    /// <param name="a">The first number.</param>
+    /// <returns>The sum.</returns>
+    public static int Add(int a, int b) => a + b;
+
+    // Not a doc comment.
+    public const string Slashes = "/// not one either";
+
+    public static int Answer = Add(40, 2);
+}
@@ -0,0 +1,19 @@
+<!-- 19 lines 12 code 5 comments 2 blanks -->
+<%@ page contentType="text/html" %>
+<html>
+  <body>
+    <%-- JSP comment --%>
+    <%
+      // Java comment
+      String name = request.getParameter("name");
+
+      /* Multi-line
+         Java comment */
+      if (name == null) {
+        name = "world";
+      }
+    %>
+
+    <p>Hello, <%= name %></p>
+  </body>
+</html>
@@ -0,0 +1,29 @@
+// 29 lines 21 code 4 comments 4 blanks
+import styled from 'styled-components';
+import { gql } from '@apollo/client';
+
+// A styled component.
+const Button = styled.button`
+  /* Rounded corners */
+  border-radius: ${(props) => (props.round ? `${props.size}px` : '0')};
+  color: red;
+`;
+
+const QUERY = gql`
+  # Fetch the user
+  query User($id: ID!) {
+    user(id: $id) {
+      name
+    }
+  }
+`;
+
+const inline = css`color: blue;`;
+const text = `not
+a tagged template`;
+
+export function App() {
+  return (
+    <Button onClick={() => fetch(QUERY)}>{text}</Button>
+  );
+}
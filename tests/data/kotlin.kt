@@ -0,0 +1,22 @@
+// 22 lines 10 code 8 comments 4 blanks
+
+/**
+ * Adds two numbers.
+ *
+ * ```kotlin
+ * val three = add(1, 2)
+ * ```
+ */
+fun add(a: Int, b: Int): Int {
+    /* Not a doc comment. */
+    return a + b
+}
+
+/** A one line doc comment. */
+val answer = add(40, 2)
+
+fun main() {
+    val text = """
+        /** Not a doc comment either. */
+    """
+}
@@ -0,0 +1,26 @@
+<!-- 26 lines 19 code 5 comments 2 blanks -->
+<!DOCTYPE html>
+<html>
+  <head>
+    <title><?= $title ?></title>
+    <style>
+      /* CSS comment */
+      body { margin: 0; }
+    </style>
+  </head>
+
+  <body>
+<?php
+// PHP comment
+$items = ["a", "b"];
+
+# Another PHP comment
+foreach ($items as $item) {
+    echo "<li>$item</li>";
+}
+?>
+    <!-- HTML comment -->
+    <ul></ul>
+    <script>alert("?>");</script>
+  </body>
+</html>